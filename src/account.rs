@@ -1,26 +1,47 @@
-#[derive(Debug, Clone, Copy, PartialEq)]
+use crate::currency::Currency;
+use crate::lock_policies::LockPolicy;
+use crate::transaction::ClientId;
+use serde::{Deserialize, Serialize};
+
+// Balance of a client in one currency. Locking applies to the client as a whole,
+// so all accounts of a client share the same `locked` flag.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub struct Account {
-    pub client_id: u16,
+    pub client_id: ClientId,
+    pub currency: Currency,
     pub available: f32,
     pub held: f32,
     pub locked: bool,
+    // policy that locked the client automatically, if any
+    #[serde(default)]
+    pub lock_reason: Option<LockPolicy>,
 }
 
 impl Account {
-    pub fn new_with_client(client_id: u16) -> Account {
+    pub fn new_with_client(client_id: ClientId, currency: Currency) -> Account {
         Account {
             client_id,
+            currency,
             available: 0.0,
             held: 0.0,
             locked: false,
+            lock_reason: None,
         }
     }
-    pub fn new(client_id: u16, available: f32, held: f32, locked: bool) -> Account {
+    pub fn new(
+        client_id: ClientId,
+        currency: Currency,
+        available: f32,
+        held: f32,
+        locked: bool,
+    ) -> Account {
         Account {
             client_id,
+            currency,
             available,
             held,
             locked,
+            lock_reason: None,
         }
     }
 }
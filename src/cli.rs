@@ -0,0 +1,238 @@
+use crate::error_budget::ErrorBudget;
+use crate::run_summary::SummaryFormat;
+use crate::transaction::ClientId;
+use crate::transaction_parser::CsvDialect;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+
+pub const USAGE: &str = "usage:
+    transaction-resolution [options] <input.csv>
+    transaction-resolution trial-balance [options] [<input.csv>]
+    transaction-resolution statement --client <id> [options] [<input.csv>]
+    transaction-resolution validate [options] <input.csv>
+    transaction-resolution accrue-interest --interest-rates <rates.csv> [--client-tiers <tiers.csv>] [options] [<input.csv>]
+options:
+    --admin                            accept lock, unlock and adjustment rows
+    --ledger <dir>                     keep the ledger in a sled database
+    --fx-rates <rates.csv>             convert withdrawals from the currencies a client holds
+    --fees <fees.csv>                  charge fees on deposits and withdrawals
+    --fee-account <id>                 client the fees are credited to
+    --limits <limits.csv>              per-client overdraft and withdrawal limits
+    --rules <rules.csv>                flag, hold or reject transactions by rule
+    --lock-policies <policies.csv>     lock clients automatically
+    --as-of <yyyy-mm-dd>               start processing on this date instead of today
+    --schema-version <n>               columns the input has, the latest version by default
+    --delimiter <char>                 field delimiter of the input, `tab` for tabs
+    --quote <char>                     quote character of the input
+    --comment <char>                   skip input lines starting with it
+    --no-header                        the input has no header, its columns are in schema order
+    --type-aliases <aliases.csv>       partner names of transaction types
+    --dead-letter <rejected.csv>       write input rows that can not be parsed to this file
+    --resubmit                         the input is a dead-letter file, drop its line and error columns
+    --error-budget <n|n%>              give up, without balances, when more rows fail
+    --summary <text|json>              print statistics of the run to stderr
+    --metrics <address>                serve Prometheus metrics on http://<address>/metrics";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    // process the input and print the resulting balances
+    Process,
+    // process the input and print the history of one client
+    Statement {
+        client_id: ClientId,
+    },
+    // process the input and print the balance of every ledger account
+    TrialBalance,
+    // process the input against a copy of the ledger and print what it would change
+    Validate,
+    // process the input, book a day of interest and print the resulting balances
+    AccrueInterest {
+        interest_rates: String,
+        client_tiers: Option<String>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    pub command: Command,
+    pub input: Option<String>,
+    pub ledger: Option<String>,
+    pub admin: bool,
+    pub fx_rates: Option<String>,
+    pub fees: Option<String>,
+    pub fee_account: Option<ClientId>,
+    pub limits: Option<String>,
+    pub rules: Option<String>,
+    pub lock_policies: Option<String>,
+    pub as_of: Option<NaiveDate>,
+    pub schema_version: Option<u32>,
+    pub dialect: CsvDialect,
+    pub type_aliases: Option<String>,
+    pub dead_letter: Option<String>,
+    pub resubmit: bool,
+    pub error_budget: Option<ErrorBudget>,
+    pub summary: Option<SummaryFormat>,
+    pub metrics: Option<String>,
+}
+
+impl Options {
+    // `args` are the command line arguments without the program name
+    pub fn parse(args: &[String]) -> anyhow::Result<Options> {
+        let mut args = args.iter();
+        let mut subcommand = None;
+        let mut client_id = None;
+        let mut input = None;
+        let mut ledger = None;
+        let mut admin = false;
+        let mut fx_rates = None;
+        let mut fees = None;
+        let mut fee_account = None;
+        let mut limits = None;
+        let mut rules = None;
+        let mut lock_policies = None;
+        let mut as_of = None;
+        let mut schema_version = None;
+        let mut dialect = CsvDialect::default();
+        let mut type_aliases = None;
+        let mut dead_letter = None;
+        let mut resubmit = false;
+        let mut error_budget = None;
+        let mut summary = None;
+        let mut metrics = None;
+        let mut interest_rates = None;
+        let mut client_tiers = None;
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "statement" | "trial-balance" | "validate" | "accrue-interest"
+                    if subcommand.is_none() && input.is_none() =>
+                {
+                    subcommand = Some(arg.as_str())
+                }
+                "--client" => client_id = Some(Options::client_id_of(arg, args.next())?),
+                "--ledger" => ledger = Some(Options::value_of(arg, args.next())?.clone()),
+                "--admin" => admin = true,
+                "--fx-rates" => fx_rates = Some(Options::value_of(arg, args.next())?.clone()),
+                "--fees" => fees = Some(Options::value_of(arg, args.next())?.clone()),
+                "--fee-account" => fee_account = Some(Options::client_id_of(arg, args.next())?),
+                "--limits" => limits = Some(Options::value_of(arg, args.next())?.clone()),
+                "--rules" => rules = Some(Options::value_of(arg, args.next())?.clone()),
+                "--lock-policies" => {
+                    lock_policies = Some(Options::value_of(arg, args.next())?.clone())
+                }
+                "--interest-rates" => {
+                    interest_rates = Some(Options::value_of(arg, args.next())?.clone())
+                }
+                "--client-tiers" => {
+                    client_tiers = Some(Options::value_of(arg, args.next())?.clone())
+                }
+                "--as-of" => as_of = Some(Options::date_of(arg, args.next())?),
+                "--schema-version" => {
+                    let value = Options::value_of(arg, args.next())?;
+                    schema_version = Some(
+                        value
+                            .parse::<u32>()
+                            .map_err(|_| anyhow!("invalid schema version: {}", value))?,
+                    )
+                }
+                "--delimiter" => dialect.delimiter = Options::char_of(arg, args.next())?,
+                "--quote" => dialect.quote = Options::char_of(arg, args.next())?,
+                "--comment" => dialect.comment = Some(Options::char_of(arg, args.next())?),
+                "--no-header" => dialect.has_header = false,
+                "--dead-letter" => dead_letter = Some(Options::value_of(arg, args.next())?.clone()),
+                "--resubmit" => resubmit = true,
+                "--error-budget" => {
+                    error_budget = Some(Options::value_of(arg, args.next())?.parse()?)
+                }
+                "--summary" => summary = Some(Options::value_of(arg, args.next())?.parse()?),
+                "--metrics" => metrics = Some(Options::value_of(arg, args.next())?.clone()),
+                "--type-aliases" => {
+                    type_aliases = Some(Options::value_of(arg, args.next())?.clone())
+                }
+                flag if flag.starts_with("--") => return Err(anyhow!("unknown option: {}", flag)),
+                path if input.is_none() => input = Some(path.to_string()),
+                extra => return Err(anyhow!("unexpected argument: {}", extra)),
+            }
+        }
+        if client_id.is_some() && subcommand != Some("statement") {
+            return Err(anyhow!("--client is only supported by statement"));
+        }
+        if (interest_rates.is_some() || client_tiers.is_some())
+            && subcommand != Some("accrue-interest")
+        {
+            return Err(anyhow!(
+                "--interest-rates and --client-tiers are only supported by accrue-interest"
+            ));
+        }
+        // a validation must not leave anything behind
+        if dead_letter.is_some() && subcommand == Some("validate") {
+            return Err(anyhow!("--dead-letter is not supported by validate"));
+        }
+        let command = match subcommand {
+            Some("statement") => match client_id {
+                Some(client_id) => Command::Statement { client_id },
+                None => return Err(anyhow!("statement requires --client <id>")),
+            },
+            Some("accrue-interest") => match interest_rates {
+                Some(interest_rates) => Command::AccrueInterest {
+                    interest_rates,
+                    client_tiers,
+                },
+                None => {
+                    return Err(anyhow!(
+                        "accrue-interest requires --interest-rates <rates.csv>"
+                    ))
+                }
+            },
+            Some("validate") if input.is_none() => return Err(anyhow!("missing input file")),
+            Some("validate") => Command::Validate,
+            Some(_) => Command::TrialBalance,
+            None if input.is_none() => return Err(anyhow!("missing input file")),
+            None => Command::Process,
+        };
+        Ok(Options {
+            command,
+            input,
+            ledger,
+            admin,
+            fx_rates,
+            fees,
+            fee_account,
+            limits,
+            rules,
+            lock_policies,
+            as_of,
+            schema_version,
+            dialect,
+            type_aliases,
+            dead_letter,
+            resubmit,
+            error_budget,
+            summary,
+            metrics,
+        })
+    }
+    fn client_id_of(flag: &str, value: Option<&String>) -> anyhow::Result<ClientId> {
+        let value = Options::value_of(flag, value)?;
+        value
+            .parse::<ClientId>()
+            .map_err(|_| anyhow!("invalid client id: {}", value))
+    }
+    fn date_of(flag: &str, value: Option<&String>) -> anyhow::Result<NaiveDate> {
+        let value = Options::value_of(flag, value)?;
+        value
+            .parse::<NaiveDate>()
+            .map_err(|_| anyhow!("invalid date: {}", value))
+    }
+    // a single ascii character, as the csv reader works on bytes
+    fn char_of(flag: &str, value: Option<&String>) -> anyhow::Result<u8> {
+        let value = Options::value_of(flag, value)?;
+        match value.as_bytes() {
+            b"tab" => Ok(b'\t'),
+            [byte] if byte.is_ascii() => Ok(*byte),
+            _ => Err(anyhow!("invalid character: {}", value)),
+        }
+    }
+    fn value_of<'a>(flag: &str, value: Option<&'a String>) -> anyhow::Result<&'a String> {
+        value.ok_or_else(|| anyhow!("{} requires a value", flag))
+    }
+}
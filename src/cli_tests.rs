@@ -0,0 +1,158 @@
+#![cfg(test)]
+mod tests {
+    use crate::cli::{Command, Options};
+    use crate::error_budget::ErrorBudget;
+    use crate::run_summary::SummaryFormat;
+    use crate::transaction_parser::CsvDialect;
+    use chrono::NaiveDate;
+
+    fn parse(args: &[&str]) -> anyhow::Result<Options> {
+        Options::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+    }
+    #[test]
+    fn test_parse_process() {
+        assert_eq!(
+            Options {
+                command: Command::Process,
+                input: Some("transactions.csv".to_string()),
+                ledger: Some("ledger".to_string()),
+                admin: true,
+                fx_rates: Some("rates.csv".to_string()),
+                fees: Some("fees.csv".to_string()),
+                fee_account: Some(0),
+                limits: Some("limits.csv".to_string()),
+                rules: Some("rules.csv".to_string()),
+                lock_policies: Some("policies.csv".to_string()),
+                as_of: NaiveDate::from_ymd_opt(2024, 3, 1),
+                schema_version: Some(1),
+                dialect: CsvDialect {
+                    delimiter: b';',
+                    quote: b'\'',
+                    comment: Some(b'#'),
+                    has_header: false,
+                },
+                type_aliases: Some("aliases.csv".to_string()),
+                dead_letter: Some("rejected.csv".to_string()),
+                resubmit: true,
+                error_budget: Some(ErrorBudget::Percent(2.5)),
+                summary: Some(SummaryFormat::Json),
+                metrics: Some("127.0.0.1:9100".to_string()),
+            },
+            parse(&[
+                "--admin",
+                "transactions.csv",
+                "--ledger",
+                "ledger",
+                "--fx-rates",
+                "rates.csv",
+                "--fees",
+                "fees.csv",
+                "--fee-account",
+                "0",
+                "--limits",
+                "limits.csv",
+                "--rules",
+                "rules.csv",
+                "--lock-policies",
+                "policies.csv",
+                "--as-of",
+                "2024-03-01",
+                "--schema-version",
+                "1",
+                "--delimiter",
+                ";",
+                "--quote",
+                "'",
+                "--comment",
+                "#",
+                "--no-header",
+                "--type-aliases",
+                "aliases.csv",
+                "--dead-letter",
+                "rejected.csv",
+                "--resubmit",
+                "--error-budget",
+                "2.5%",
+                "--summary",
+                "json",
+                "--metrics",
+                "127.0.0.1:9100"
+            ])
+            .unwrap()
+        );
+    }
+    #[test]
+    fn test_parse_statement() {
+        assert_eq!(
+            Options {
+                command: Command::Statement { client_id: 2 },
+                input: None,
+                ledger: Some("ledger".to_string()),
+                admin: false,
+                fx_rates: None,
+                fees: None,
+                fee_account: None,
+                limits: None,
+                rules: None,
+                lock_policies: None,
+                as_of: None,
+                schema_version: None,
+                dialect: CsvDialect::default(),
+                type_aliases: None,
+                dead_letter: None,
+                resubmit: false,
+                error_budget: None,
+                summary: None,
+                metrics: None,
+            },
+            parse(&["statement", "--client", "2", "--ledger", "ledger"]).unwrap()
+        );
+    }
+    #[test]
+    fn test_parse_trial_balance() {
+        assert_eq!(
+            Command::TrialBalance,
+            parse(&["trial-balance", "transactions.csv"])
+                .unwrap()
+                .command
+        );
+    }
+    #[test]
+    fn test_parse_validate() {
+        assert_eq!(
+            Command::Validate,
+            parse(&["validate", "transactions.csv", "--ledger", "ledger"])
+                .unwrap()
+                .command
+        );
+        assert!(parse(&["validate", "--ledger", "ledger"]).is_err());
+        assert!(parse(&[
+            "validate",
+            "transactions.csv",
+            "--dead-letter",
+            "rejected.csv"
+        ])
+        .is_err());
+    }
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse(&[]).is_err());
+        assert!(parse(&["statement", "transactions.csv"]).is_err());
+        assert!(parse(&["statement", "--client", "foo"]).is_err());
+        assert!(parse(&["--client", "2", "transactions.csv"]).is_err());
+        assert!(parse(&["trial-balance", "--client", "2"]).is_err());
+        assert!(parse(&["transactions.csv", "--unknown"]).is_err());
+        assert!(parse(&["transactions.csv", "--ledger"]).is_err());
+        assert!(parse(&["transactions.csv", "--as-of", "2024-02-30"]).is_err());
+        assert!(parse(&["transactions.csv", "--schema-version", "v2"]).is_err());
+        assert!(parse(&["transactions.csv", "--delimiter", ";;"]).is_err());
+        assert!(parse(&["transactions.csv", "--summary", "yaml"]).is_err());
+        assert_eq!(
+            b'\t',
+            parse(&["transactions.csv", "--delimiter", "tab"])
+                .unwrap()
+                .dialect
+                .delimiter
+        );
+    }
+}
@@ -0,0 +1,82 @@
+use crate::currency::Currency;
+use crate::transaction::ClientId;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+struct ClientLimitsDTO {
+    client: ClientId,
+    #[serde(default)]
+    currency: Option<Currency>,
+    #[serde(default)]
+    overdraft_limit: Option<f32>,
+    #[serde(default)]
+    max_withdrawal: Option<f32>,
+    #[serde(default)]
+    daily_withdrawal_cap: Option<f32>,
+}
+
+// Withdrawal limits agreed with a client for one of its accounts, in the account currency
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ClientLimits {
+    // how far below zero the available funds may go
+    pub overdraft_limit: f32,
+    pub max_withdrawal: Option<f32>,
+    pub daily_withdrawal_cap: Option<f32>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ClientLimitsTable {
+    limit_map: HashMap<(ClientId, Currency), ClientLimits>,
+}
+
+impl ClientLimitsTable {
+    pub fn new() -> ClientLimitsTable {
+        ClientLimitsTable::default()
+    }
+    pub fn load(path: &str) -> anyhow::Result<ClientLimitsTable> {
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+        let mut limits_table = ClientLimitsTable::new();
+        for result in reader.deserialize() {
+            let dto: ClientLimitsDTO = result?;
+            let limits = ClientLimits {
+                overdraft_limit: dto.overdraft_limit.unwrap_or(0.0),
+                max_withdrawal: dto.max_withdrawal,
+                daily_withdrawal_cap: dto.daily_withdrawal_cap,
+            };
+            if limits.overdraft_limit < 0.0
+                || limits.max_withdrawal.unwrap_or(0.0) < 0.0
+                || limits.daily_withdrawal_cap.unwrap_or(0.0) < 0.0
+            {
+                return Err(anyhow!(
+                    "Invalid client limits: client {}, limits must not be negative",
+                    dto.client
+                ));
+            }
+            limits_table.insert(dto.client, dto.currency.unwrap_or_default(), limits);
+        }
+        Ok(limits_table)
+    }
+    pub fn insert(&mut self, client_id: ClientId, currency: Currency, limits: ClientLimits) {
+        self.limit_map.insert((client_id, currency), limits);
+    }
+    // clients without an entry get no overdraft and no withdrawal caps
+    pub fn get(&self, client_id: ClientId, currency: Currency) -> ClientLimits {
+        self.limit_map
+            .get(&(client_id, currency))
+            .copied()
+            .unwrap_or_default()
+    }
+}
+
+// Total withdrawn from an account on one day, kept to enforce the daily withdrawal cap
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct DailyWithdrawal {
+    pub client_id: ClientId,
+    pub currency: Currency,
+    pub date: NaiveDate,
+    pub amount: f32,
+}
@@ -0,0 +1,39 @@
+#![cfg(test)]
+mod tests {
+    use crate::client_limits::{ClientLimits, ClientLimitsTable};
+    use crate::currency::Currency;
+
+    #[test]
+    fn test_load_client_limits() {
+        let limits_table = ClientLimitsTable::load("test-client-limits.csv").unwrap();
+        assert_eq!(
+            ClientLimits {
+                overdraft_limit: 50.0,
+                max_withdrawal: None,
+                daily_withdrawal_cap: None,
+            },
+            limits_table.get(1, Currency::Eur)
+        );
+        assert_eq!(
+            Some(100.0),
+            limits_table.get(2, Currency::Eur).max_withdrawal
+        );
+        assert_eq!(
+            Some(150.0),
+            limits_table.get(3, Currency::Eur).daily_withdrawal_cap
+        );
+        assert_eq!(
+            ClientLimits {
+                overdraft_limit: 10.0,
+                max_withdrawal: Some(20.0),
+                daily_withdrawal_cap: Some(30.0),
+            },
+            limits_table.get(3, Currency::Usd)
+        );
+    }
+    #[test]
+    fn test_no_client_limits() {
+        let limits_table = ClientLimitsTable::new();
+        assert_eq!(ClientLimits::default(), limits_table.get(1, Currency::Eur));
+    }
+}
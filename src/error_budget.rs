@@ -0,0 +1,133 @@
+use crate::transaction::Transaction;
+use anyhow::anyhow;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeSet;
+use std::fmt;
+use std::str::FromStr;
+
+// How many input rows may fail, as corrupted rows or rejected transactions, before a run
+// is given up
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ErrorBudget {
+    Count(u64),
+    // of the input rows, only known to be exceeded once the whole input is read
+    Percent(f32),
+}
+
+impl FromStr for ErrorBudget {
+    type Err = anyhow::Error;
+    // `10` for ten failed rows, `5%` for five percent of the rows
+    fn from_str(value: &str) -> anyhow::Result<ErrorBudget> {
+        let budget = match value.strip_suffix('%') {
+            Some(percent) => percent
+                .parse::<f32>()
+                .ok()
+                .filter(|percent| (0.0..=100.0).contains(percent))
+                .map(ErrorBudget::Percent),
+            None => value.parse::<u64>().ok().map(ErrorBudget::Count),
+        };
+        budget.ok_or_else(|| anyhow!("invalid error budget: {}", value))
+    }
+}
+
+impl fmt::Display for ErrorBudget {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorBudget::Count(count) => write!(f, "{}", count),
+            ErrorBudget::Percent(percent) => write!(f, "{}%", percent),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ErrorCounts {
+    pub rows: u64,
+    pub corrupted: u64,
+    pub rejected: u64,
+}
+
+impl ErrorCounts {
+    pub fn errors(&self) -> u64 {
+        self.corrupted + self.rejected
+    }
+}
+
+impl fmt::Display for ErrorCounts {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} of {} rows failed, {} corrupted and {} rejected",
+            self.errors(),
+            self.rows,
+            self.corrupted,
+            self.rejected
+        )
+    }
+}
+
+impl ErrorBudget {
+    pub fn exceeded(&self, counts: &ErrorCounts, input_done: bool) -> bool {
+        match self {
+            ErrorBudget::Count(count) => counts.errors() > *count,
+            ErrorBudget::Percent(percent) => {
+                input_done && counts.errors() as f64 * 100.0 > *percent as f64 * counts.rows as f64
+            }
+        }
+    }
+}
+
+// Where a run of an input that was given up stopped, so a rerun of the fixed input skips the
+// rows it applied. Rows are counted from 1 in the order the parser sent them, corrupted ones
+// included, so the fixed input must keep every row in its place.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub input: String,
+    // rows the runs of the input took from the parser
+    pub consumed: u64,
+    // rows up to `consumed` that were corrupted or rejected, and are processed again
+    pub failed: BTreeSet<u64>,
+    // of the applied rows and their place in the input, a rerun stops when its rows differ
+    pub fingerprint: u64,
+}
+
+impl Checkpoint {
+    pub fn new(input: &str) -> Checkpoint {
+        Checkpoint {
+            input: input.to_string(),
+            consumed: 0,
+            failed: BTreeSet::new(),
+            fingerprint: 0,
+        }
+    }
+    pub fn applied(&self, row: u64) -> bool {
+        row <= self.consumed && !self.failed.contains(&row)
+    }
+    // the transaction is only given for a row that was applied
+    pub fn record(&mut self, row: u64, applied: Option<&Transaction>) {
+        self.consumed = self.consumed.max(row);
+        match applied {
+            Some(transaction) => {
+                self.failed.remove(&row);
+                self.fingerprint = Checkpoint::add_row(self.fingerprint, row, transaction);
+            }
+            None => {
+                self.failed.insert(row);
+            }
+        }
+    }
+    // Adds an applied row to a fingerprint. Rows are summed up, as a failed row applied by a
+    // rerun comes after rows applied before it
+    pub fn add_row(fingerprint: u64, row: u64, transaction: &Transaction) -> u64 {
+        // FNV-1a, stable across runs and builds unlike the std hasher
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for byte in row
+            .to_le_bytes()
+            .iter()
+            .chain(format!("{:?}", transaction).as_bytes())
+        {
+            hash ^= *byte as u64;
+            hash = hash.wrapping_mul(0x0100_0000_01b3);
+        }
+        fingerprint.wrapping_add(hash)
+    }
+}
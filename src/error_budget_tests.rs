@@ -0,0 +1,30 @@
+#![cfg(test)]
+mod tests {
+    use crate::error_budget::{ErrorBudget, ErrorCounts};
+
+    #[test]
+    fn test_parse_error_budget() {
+        assert_eq!(ErrorBudget::Count(10), "10".parse::<ErrorBudget>().unwrap());
+        assert_eq!(
+            ErrorBudget::Percent(2.5),
+            "2.5%".parse::<ErrorBudget>().unwrap()
+        );
+        for invalid in ["-1", "ten", "101%", "%"] {
+            assert!(invalid.parse::<ErrorBudget>().is_err());
+        }
+    }
+    #[test]
+    fn test_error_budget_exceeded() {
+        let counts = ErrorCounts {
+            rows: 10,
+            corrupted: 1,
+            rejected: 1,
+        };
+        assert!(!ErrorBudget::Count(2).exceeded(&counts, false));
+        assert!(ErrorBudget::Count(1).exceeded(&counts, false));
+        // a share of the rows is only known at the end of the input
+        assert!(!ErrorBudget::Percent(10.0).exceeded(&counts, false));
+        assert!(ErrorBudget::Percent(10.0).exceeded(&counts, true));
+        assert!(!ErrorBudget::Percent(20.0).exceeded(&counts, true));
+    }
+}
@@ -0,0 +1,88 @@
+use crate::transaction::{round_amount, ClientId, TransactionType};
+use anyhow::anyhow;
+use csv::{ReaderBuilder, Trim};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// fees are booked on this client unless configured otherwise
+pub const DEFAULT_HOUSE_CLIENT_ID: ClientId = ClientId::MAX;
+
+#[derive(Deserialize, Debug)]
+struct FeeTierDTO {
+    #[serde(rename = "type")]
+    of_type: TransactionType,
+    from_amount: f32,
+    flat: f32,
+    percentage: f32,
+}
+
+// Fee charged on amounts starting at `from_amount`, up to the next tier of the same type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FeeTier {
+    pub from_amount: f32,
+    pub flat: f32,
+    pub percentage: f32,
+}
+
+// Fees per transaction type. A single tier starting at 0 is a plain flat and/or
+// percentage fee, several tiers make the fee depend on the transaction amount.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FeeSchedule {
+    // None without a loaded schedule, no client is reserved for fee income then
+    pub house_client_id: Option<ClientId>,
+    tier_map: HashMap<TransactionType, Vec<FeeTier>>,
+}
+
+impl FeeSchedule {
+    pub fn new() -> FeeSchedule {
+        FeeSchedule::default()
+    }
+    pub fn load(path: &str, house_client_id: ClientId) -> anyhow::Result<FeeSchedule> {
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+        let mut fee_schedule = FeeSchedule {
+            house_client_id: Some(house_client_id),
+            ..FeeSchedule::new()
+        };
+        for result in reader.deserialize() {
+            let dto: FeeTierDTO = result?;
+            if dto.of_type != TransactionType::Deposit && dto.of_type != TransactionType::Withdrawal
+            {
+                return Err(anyhow!(
+                    "Invalid fee tier: fees are only charged on deposits and withdrawals, not {}",
+                    dto.of_type
+                ));
+            }
+            if dto.from_amount < 0.0 || dto.flat < 0.0 || dto.percentage < 0.0 {
+                return Err(anyhow!(
+                    "Invalid fee tier: {} from {}, values must not be negative",
+                    dto.of_type,
+                    dto.from_amount
+                ));
+            }
+            fee_schedule.insert(
+                dto.of_type,
+                FeeTier {
+                    from_amount: dto.from_amount,
+                    flat: dto.flat,
+                    percentage: dto.percentage,
+                },
+            );
+        }
+        Ok(fee_schedule)
+    }
+    pub fn insert(&mut self, of_type: TransactionType, tier: FeeTier) {
+        let tiers = self.tier_map.entry(of_type).or_default();
+        tiers.push(tier);
+        tiers.sort_by(|a, b| a.from_amount.total_cmp(&b.from_amount));
+    }
+    pub fn fee(&self, of_type: TransactionType, amount: f32) -> f32 {
+        let tier = self
+            .tier_map
+            .get(&of_type)
+            .and_then(|tiers| tiers.iter().rev().find(|tier| tier.from_amount <= amount));
+        match tier {
+            Some(tier) => round_amount(tier.flat + amount * tier.percentage / 100.0),
+            None => 0.0,
+        }
+    }
+}
@@ -0,0 +1,21 @@
+#![cfg(test)]
+mod tests {
+    use crate::fee_schedule::FeeSchedule;
+    use crate::transaction::TransactionType;
+
+    #[test]
+    fn test_fee_tiers() {
+        let fee_schedule = FeeSchedule::load("test-fees.csv", 0).unwrap();
+        assert_eq!(Some(0), fee_schedule.house_client_id);
+        assert_eq!(0.5, fee_schedule.fee(TransactionType::Deposit, 10.0));
+        assert_eq!(3.0, fee_schedule.fee(TransactionType::Withdrawal, 100.0));
+        assert_eq!(10.0, fee_schedule.fee(TransactionType::Withdrawal, 1000.0));
+    }
+    #[test]
+    fn test_no_fees() {
+        let fee_schedule = FeeSchedule::new();
+        assert_eq!(None, fee_schedule.house_client_id);
+        assert_eq!(0.0, fee_schedule.fee(TransactionType::Deposit, 10.0));
+        assert_eq!(0.0, fee_schedule.fee(TransactionType::Withdrawal, 10.0));
+    }
+}
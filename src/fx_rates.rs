@@ -0,0 +1,66 @@
+use crate::currency::Currency;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use csv::{ReaderBuilder, Trim};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+struct FxRateDTO {
+    from: Currency,
+    to: Currency,
+    rate: f32,
+    effective_date: NaiveDate,
+}
+
+// Conversion rates between currencies, each effective from a given date until the
+// next rate of the same pair takes over. `rate` is the amount of `to` per one `from`.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct FxRates {
+    rate_map: HashMap<(Currency, Currency), Vec<(NaiveDate, f32)>>,
+}
+
+impl FxRates {
+    pub fn new() -> FxRates {
+        FxRates::default()
+    }
+    pub fn load(path: &str) -> anyhow::Result<FxRates> {
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+        let mut fx_rates = FxRates::new();
+        for result in reader.deserialize() {
+            let dto: FxRateDTO = result?;
+            if dto.rate <= 0.0 {
+                return Err(anyhow!(
+                    "Invalid fx rate: {} -> {} on {}, rate must be positive",
+                    dto.from,
+                    dto.to,
+                    dto.effective_date
+                ));
+            }
+            fx_rates.insert(dto.from, dto.to, dto.effective_date, dto.rate);
+        }
+        Ok(fx_rates)
+    }
+    pub fn insert(&mut self, from: Currency, to: Currency, effective_date: NaiveDate, rate: f32) {
+        let rates = self.rate_map.entry((from, to)).or_default();
+        rates.push((effective_date, rate));
+        rates.sort_by_key(|(date, _)| *date);
+    }
+    // Rate effective on the given date. Falls back to the inverse of the opposite
+    // pair, so a table only needs one direction per pair.
+    pub fn rate(&self, from: Currency, to: Currency, on: NaiveDate) -> Option<f32> {
+        if from == to {
+            return Some(1.0);
+        }
+        FxRates::effective_rate(self.rate_map.get(&(from, to)), on).or_else(|| {
+            FxRates::effective_rate(self.rate_map.get(&(to, from)), on).map(|rate| 1.0 / rate)
+        })
+    }
+    fn effective_rate(rates: Option<&Vec<(NaiveDate, f32)>>, on: NaiveDate) -> Option<f32> {
+        rates?
+            .iter()
+            .rev()
+            .find(|(effective_date, _)| *effective_date <= on)
+            .map(|(_, rate)| *rate)
+    }
+}
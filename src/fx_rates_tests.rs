@@ -0,0 +1,42 @@
+#![cfg(test)]
+mod tests {
+    use crate::currency::Currency;
+    use crate::fx_rates::FxRates;
+    use chrono::NaiveDate;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+    #[test]
+    fn test_effective_rate() {
+        let fx_rates = FxRates::load("test-fx-rates.csv").unwrap();
+        assert_eq!(
+            None,
+            fx_rates.rate(Currency::Eur, Currency::Usd, date(2019, 12, 31))
+        );
+        assert_eq!(
+            Some(1.1),
+            fx_rates.rate(Currency::Eur, Currency::Usd, date(2020, 12, 31))
+        );
+        assert_eq!(
+            Some(1.2),
+            fx_rates.rate(Currency::Eur, Currency::Usd, date(2021, 1, 1))
+        );
+    }
+    #[test]
+    fn test_inverse_and_identity_rate() {
+        let fx_rates = FxRates::load("test-fx-rates.csv").unwrap();
+        assert_eq!(
+            Some(1.0 / 1.15),
+            fx_rates.rate(Currency::Eur, Currency::Gbp, date(2022, 1, 1))
+        );
+        assert_eq!(
+            Some(1.0),
+            fx_rates.rate(Currency::Usd, Currency::Usd, date(2022, 1, 1))
+        );
+        assert_eq!(
+            None,
+            fx_rates.rate(Currency::Usd, Currency::Gbp, date(2022, 1, 1))
+        );
+    }
+}
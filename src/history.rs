@@ -0,0 +1,105 @@
+use crate::account::Account;
+use crate::transaction::Transaction;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+// One processed event of a client together with the account state right after it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub transaction: Transaction,
+    pub account: Account,
+    pub accepted: bool,
+    // not accepted yet, kept out of the ledger by a rule until an admin releases it
+    #[serde(default)]
+    pub held: bool,
+    // not applied yet, waiting for the processing date to reach its effective date
+    #[serde(default)]
+    pub scheduled: bool,
+    pub reason: Option<String>,
+    // rules that flagged the transaction without stopping it
+    #[serde(default)]
+    pub flags: Vec<String>,
+}
+
+impl HistoryEntry {
+    pub fn accepted(transaction: Transaction, account: Account) -> HistoryEntry {
+        HistoryEntry {
+            transaction,
+            account,
+            accepted: true,
+            held: false,
+            scheduled: false,
+            reason: None,
+            flags: vec![],
+        }
+    }
+    pub fn rejected(transaction: Transaction, account: Account, reason: &str) -> HistoryEntry {
+        HistoryEntry {
+            transaction,
+            account,
+            accepted: false,
+            held: false,
+            scheduled: false,
+            reason: Some(reason.to_string()),
+            flags: vec![],
+        }
+    }
+    pub fn held(transaction: Transaction, account: Account, reason: &str) -> HistoryEntry {
+        HistoryEntry {
+            held: true,
+            ..HistoryEntry::rejected(transaction, account, reason)
+        }
+    }
+    pub fn scheduled(transaction: Transaction, account: Account) -> HistoryEntry {
+        HistoryEntry {
+            transaction,
+            account,
+            accepted: false,
+            held: false,
+            scheduled: true,
+            reason: None,
+            flags: vec![],
+        }
+    }
+    pub fn status(&self) -> &'static str {
+        if self.accepted {
+            "accepted"
+        } else if self.held {
+            "held"
+        } else if self.scheduled {
+            "scheduled"
+        } else {
+            "rejected"
+        }
+    }
+}
+
+// Number of transactions of a run per status and reason
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Outcomes {
+    count_map: BTreeMap<(String, String), u64>,
+}
+
+impl Outcomes {
+    pub fn new() -> Outcomes {
+        Outcomes::default()
+    }
+    pub fn record(&mut self, status: &str, reason: Option<&str>) {
+        *self
+            .count_map
+            .entry((status.to_string(), reason.unwrap_or_default().to_string()))
+            .or_default() += 1;
+    }
+    pub fn count(&self, status: &str, reason: Option<&str>) -> u64 {
+        self.count_map
+            .get(&(status.to_string(), reason.unwrap_or_default().to_string()))
+            .copied()
+            .unwrap_or(0)
+    }
+    // (status, reason, count) ordered by status and reason, an empty reason when there is none
+    pub fn counts(&self) -> impl Iterator<Item = (&str, &str, u64)> {
+        self.count_map
+            .iter()
+            .map(|((status, reason), count)| (status.as_str(), reason.as_str(), *count))
+    }
+}
@@ -0,0 +1,156 @@
+use crate::currency::Currency;
+use crate::transaction::{ClientId, TransactionId};
+use anyhow::anyhow;
+use chrono::{Datelike, NaiveDate};
+use csv::{ReaderBuilder, Trim};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+// tier of clients missing from the client tiers file
+pub const DEFAULT_TIER: &str = "standard";
+// interest deposits get ids from the top of the id range, see `interest_transaction_id`
+pub const INTEREST_TRANSACTION_ID_BASE: TransactionId = 1 << 63;
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DayCount {
+    // actual days over a 360 day year
+    #[serde(rename = "act/360")]
+    Act360,
+    #[serde(rename = "act/365")]
+    Act365,
+    // actual days over the actual length of the year
+    #[serde(rename = "act/act")]
+    ActAct,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Rounding {
+    HalfUp,
+    HalfEven,
+    // towards zero, never pays out a fraction the client did not fully earn
+    Down,
+}
+
+#[derive(Deserialize, Debug)]
+struct InterestRateDTO {
+    tier: String,
+    currency: Currency,
+    annual_rate: f64,
+    day_count: DayCount,
+    rounding: Rounding,
+}
+
+#[derive(Deserialize, Debug)]
+struct ClientTierDTO {
+    client: ClientId,
+    tier: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InterestRate {
+    // percentage per year
+    pub annual_rate: f64,
+    pub day_count: DayCount,
+    pub rounding: Rounding,
+}
+
+// Interest rates per client tier and currency, and the tier of each client
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct InterestRates {
+    rate_map: HashMap<(String, Currency), InterestRate>,
+    tier_map: HashMap<ClientId, String>,
+}
+
+impl InterestRates {
+    pub fn new() -> InterestRates {
+        InterestRates::default()
+    }
+    pub fn load(rates_path: &str, tiers_path: Option<&str>) -> anyhow::Result<InterestRates> {
+        let mut interest_rates = InterestRates::new();
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(rates_path)?;
+        for result in reader.deserialize() {
+            let dto: InterestRateDTO = result?;
+            if dto.annual_rate < 0.0 {
+                return Err(anyhow!(
+                    "Invalid interest rate: {} {}, rate must not be negative",
+                    dto.tier,
+                    dto.currency
+                ));
+            }
+            interest_rates.insert_rate(
+                &dto.tier,
+                dto.currency,
+                InterestRate {
+                    annual_rate: dto.annual_rate,
+                    day_count: dto.day_count,
+                    rounding: dto.rounding,
+                },
+            );
+        }
+        if let Some(tiers_path) = tiers_path {
+            let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(tiers_path)?;
+            for result in reader.deserialize() {
+                let dto: ClientTierDTO = result?;
+                interest_rates.insert_tier(dto.client, &dto.tier);
+            }
+        }
+        Ok(interest_rates)
+    }
+    pub fn insert_rate(&mut self, tier: &str, currency: Currency, rate: InterestRate) {
+        self.rate_map.insert((tier.to_string(), currency), rate);
+    }
+    pub fn insert_tier(&mut self, client_id: ClientId, tier: &str) {
+        self.tier_map.insert(client_id, tier.to_string());
+    }
+    // One day of interest on the balance, rounded to the four decimal places amounts are
+    // kept to. Zero when the client's tier pays no interest in the currency.
+    pub fn daily_interest(
+        &self,
+        client_id: ClientId,
+        currency: Currency,
+        balance: f32,
+        on: NaiveDate,
+    ) -> f32 {
+        let tier = self
+            .tier_map
+            .get(&client_id)
+            .map(String::as_str)
+            .unwrap_or(DEFAULT_TIER);
+        let rate = match self.rate_map.get(&(tier.to_string(), currency)) {
+            Some(rate) if balance > 0.0 => rate,
+            _ => return 0.0,
+        };
+        let days_in_year = match rate.day_count {
+            DayCount::Act360 => 360.0,
+            DayCount::Act365 => 365.0,
+            DayCount::ActAct if on.leap_year() => 366.0,
+            DayCount::ActAct => 365.0,
+        };
+        let interest = balance as f64 * rate.annual_rate / 100.0 / days_in_year * 10_000.0;
+        // drop the float noise of the division first, so 0.1 does not round down to 0.0999
+        let interest = (interest * 1_000_000.0).round() / 1_000_000.0;
+        let interest = match rate.rounding {
+            Rounding::HalfUp => interest.round(),
+            Rounding::HalfEven => interest.round_ties_even(),
+            Rounding::Down => interest.trunc(),
+        };
+        (interest / 10_000.0) as f32
+    }
+}
+
+pub fn is_interest_transaction_id(id: TransactionId) -> bool {
+    id >= INTEREST_TRANSACTION_ID_BASE
+}
+
+// The same account and day always get the same id, so accruing a day twice is detected
+pub fn interest_transaction_id(
+    client_id: ClientId,
+    currency: Currency,
+    on: NaiveDate,
+) -> TransactionId {
+    INTEREST_TRANSACTION_ID_BASE
+        | (on.num_days_from_ce() as TransactionId) << 34
+        | (currency as TransactionId) << 32
+        | client_id as TransactionId
+}
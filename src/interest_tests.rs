@@ -0,0 +1,74 @@
+#![cfg(test)]
+mod tests {
+    use crate::currency::Currency;
+    use crate::interest::{interest_transaction_id, InterestRates};
+    use chrono::NaiveDate;
+
+    fn date(year: i32, month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(year, month, day).unwrap()
+    }
+    #[test]
+    fn test_daily_interest() {
+        let interest_rates =
+            InterestRates::load("test-interest-rates.csv", Some("test-client-tiers.csv")).unwrap();
+        let on = date(2023, 6, 1);
+        assert_eq!(
+            0.1,
+            interest_rates.daily_interest(1, Currency::Eur, 1000.0, on)
+        );
+        // rounded down, half up would make it 0.1
+        assert_eq!(
+            0.0999,
+            interest_rates.daily_interest(2, Currency::Eur, 999.99, on)
+        );
+        // act/act divides by the length of the year
+        assert_eq!(
+            0.1003,
+            interest_rates.daily_interest(2, Currency::Usd, 1830.0, on)
+        );
+        assert_eq!(
+            0.1,
+            interest_rates.daily_interest(2, Currency::Usd, 1830.0, date(2024, 6, 1))
+        );
+    }
+    #[test]
+    fn test_no_interest() {
+        let interest_rates =
+            InterestRates::load("test-interest-rates.csv", Some("test-client-tiers.csv")).unwrap();
+        let on = date(2023, 6, 1);
+        // the standard tier has no USD rate
+        assert_eq!(
+            0.0,
+            interest_rates.daily_interest(1, Currency::Usd, 1000.0, on)
+        );
+        assert_eq!(
+            0.0,
+            interest_rates.daily_interest(1, Currency::Eur, -1000.0, on)
+        );
+        assert_eq!(
+            0.0,
+            InterestRates::new().daily_interest(1, Currency::Eur, 1000.0, on)
+        );
+    }
+    #[test]
+    fn test_interest_transaction_id() {
+        let id = interest_transaction_id(1, Currency::Eur, date(2024, 1, 1));
+        assert!(id >= 1 << 63);
+        assert_eq!(
+            id,
+            interest_transaction_id(1, Currency::Eur, date(2024, 1, 1))
+        );
+        assert_ne!(
+            id,
+            interest_transaction_id(1, Currency::Usd, date(2024, 1, 1))
+        );
+        assert_ne!(
+            id,
+            interest_transaction_id(1, Currency::Eur, date(2024, 1, 2))
+        );
+        assert_ne!(
+            id,
+            interest_transaction_id(2, Currency::Eur, date(2024, 1, 1))
+        );
+    }
+}
@@ -0,0 +1,192 @@
+use crate::account::Account;
+use crate::currency::Currency;
+use crate::transaction::{ClientId, Transaction};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// f64 sums of f32 amounts are exact well below this, anything larger is a real imbalance
+const BALANCE_TOLERANCE: f64 = 0.0001;
+// f32 account balances are only exact to about seven significant digits
+const RELATIVE_BALANCE_TOLERANCE: f64 = 0.00001;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum LedgerAccount {
+    ClientAvailable(ClientId),
+    ClientHeld(ClientId),
+    // money entering and leaving the system through deposits and withdrawals
+    ExternalSettlement,
+    Fees,
+    // money pulled back from the system by chargebacks
+    ChargebackLoss,
+    // credits and debits booked by the support team
+    Adjustments,
+    // interest the house pays on client funds
+    InterestExpense,
+}
+
+impl fmt::Display for LedgerAccount {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LedgerAccount::ClientAvailable(client_id) => {
+                write!(f, "client {} available", client_id)
+            }
+            LedgerAccount::ClientHeld(client_id) => write!(f, "client {} held", client_id),
+            LedgerAccount::ExternalSettlement => write!(f, "external settlement"),
+            LedgerAccount::Fees => write!(f, "fees"),
+            LedgerAccount::ChargebackLoss => write!(f, "chargeback loss"),
+            LedgerAccount::Adjustments => write!(f, "adjustments"),
+            LedgerAccount::InterestExpense => write!(f, "interest expense"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Posting {
+    pub account: LedgerAccount,
+    pub currency: Currency,
+    pub amount: f64,
+}
+
+impl Posting {
+    pub fn new(account: LedgerAccount, currency: Currency, amount: f64) -> Posting {
+        Posting {
+            account,
+            currency,
+            amount,
+        }
+    }
+}
+
+// Postings of one accepted transaction, which must sum to zero per currency
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JournalEntry {
+    pub transaction: Transaction,
+    pub postings: Vec<Posting>,
+}
+
+impl JournalEntry {
+    pub fn new(transaction: Transaction, postings: Vec<Posting>) -> JournalEntry {
+        JournalEntry {
+            transaction,
+            postings: postings
+                .into_iter()
+                .filter(|posting| posting.amount != 0.0)
+                .collect(),
+        }
+    }
+    pub fn is_balanced(&self) -> bool {
+        let mut totals: BTreeMap<Currency, f64> = BTreeMap::new();
+        for posting in &self.postings {
+            *totals.entry(posting.currency).or_default() += posting.amount;
+        }
+        totals.values().all(|total| total.abs() < BALANCE_TOLERANCE)
+    }
+}
+
+// A client account whose stored balances differ from the balances its postings add up to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Discrepancy {
+    pub client_id: ClientId,
+    pub currency: Currency,
+    // (available, held) according to the journal and to the stored account
+    pub journal: (f64, f64),
+    pub account: (f64, f64),
+}
+
+impl fmt::Display for Discrepancy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "client {} {} does not reconcile: available {:.4} and held {:.4} in the journal, \
+             {:.4} and {:.4} on the account",
+            self.client_id,
+            self.currency,
+            self.journal.0,
+            self.journal.1,
+            self.account.0,
+            self.account.1
+        )
+    }
+}
+
+// Balance of every ledger account over the whole journal
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrialBalance {
+    pub balances: BTreeMap<(LedgerAccount, Currency), f64>,
+}
+
+impl TrialBalance {
+    pub fn from_journal(journal: &[JournalEntry]) -> TrialBalance {
+        let mut trial_balance = TrialBalance::default();
+        for posting in journal.iter().flat_map(|entry| &entry.postings) {
+            *trial_balance
+                .balances
+                .entry((posting.account, posting.currency))
+                .or_default() += posting.amount;
+        }
+        trial_balance
+    }
+    pub fn totals(&self) -> BTreeMap<Currency, f64> {
+        let mut totals = BTreeMap::new();
+        for ((_, currency), balance) in &self.balances {
+            *totals.entry(*currency).or_default() += balance;
+        }
+        totals
+    }
+    pub fn is_balanced(&self) -> bool {
+        self.totals()
+            .values()
+            .all(|total| total.abs() < BALANCE_TOLERANCE)
+    }
+    // Compares the client balances of the journal with the stored accounts. Fees are posted
+    // to the fees account but credited to the account of the house client.
+    pub fn reconcile(&self, accounts: &[Account], house_client_id: ClientId) -> Vec<Discrepancy> {
+        let mut balance_map: BTreeMap<(ClientId, Currency), Discrepancy> = BTreeMap::new();
+        fn entry(
+            balance_map: &mut BTreeMap<(ClientId, Currency), Discrepancy>,
+            client_id: ClientId,
+            currency: Currency,
+        ) -> &mut Discrepancy {
+            balance_map
+                .entry((client_id, currency))
+                .or_insert(Discrepancy {
+                    client_id,
+                    currency,
+                    journal: (0.0, 0.0),
+                    account: (0.0, 0.0),
+                })
+        }
+        for ((account, currency), balance) in &self.balances {
+            match account {
+                LedgerAccount::ClientAvailable(client_id) => {
+                    entry(&mut balance_map, *client_id, *currency).journal.0 += balance
+                }
+                LedgerAccount::ClientHeld(client_id) => {
+                    entry(&mut balance_map, *client_id, *currency).journal.1 += balance
+                }
+                LedgerAccount::Fees => {
+                    entry(&mut balance_map, house_client_id, *currency)
+                        .journal
+                        .0 += balance
+                }
+                _ => {}
+            }
+        }
+        for account in accounts {
+            entry(&mut balance_map, account.client_id, account.currency).account =
+                (account.available as f64, account.held as f64);
+        }
+        balance_map
+            .into_values()
+            .filter(|balances| {
+                !balances_match(balances.journal.0, balances.account.0)
+                    || !balances_match(balances.journal.1, balances.account.1)
+            })
+            .collect()
+    }
+}
+
+fn balances_match(journal: f64, account: f64) -> bool {
+    (journal - account).abs() < BALANCE_TOLERANCE + account.abs() * RELATIVE_BALANCE_TOLERANCE
+}
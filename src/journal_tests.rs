@@ -0,0 +1,109 @@
+#![cfg(test)]
+mod tests {
+    use crate::account::Account;
+    use crate::currency::Currency;
+    use crate::journal::{Discrepancy, JournalEntry, LedgerAccount, Posting, TrialBalance};
+    use crate::test_helpers::deposit;
+    use maplit::btreemap;
+
+    fn deposit_entry(id: u64, client_id: u32, amount: f64, currency: Currency) -> JournalEntry {
+        JournalEntry::new(
+            deposit(id, client_id, amount as f32),
+            vec![
+                Posting::new(LedgerAccount::ClientAvailable(client_id), currency, amount),
+                Posting::new(LedgerAccount::ExternalSettlement, currency, -amount),
+            ],
+        )
+    }
+    #[test]
+    fn test_balanced_entry() {
+        assert!(deposit_entry(1, 1, 10.0, Currency::Eur).is_balanced());
+        // a balanced total across currencies is still unbalanced per currency
+        assert!(!JournalEntry::new(
+            deposit(1, 1, 10.0),
+            vec![
+                Posting::new(LedgerAccount::ClientAvailable(1), Currency::Eur, 10.0),
+                Posting::new(LedgerAccount::ExternalSettlement, Currency::Usd, -10.0),
+            ],
+        )
+        .is_balanced());
+    }
+    #[test]
+    fn test_zero_postings_are_dropped() {
+        assert_eq!(
+            1,
+            JournalEntry::new(
+                deposit(1, 1, 10.0),
+                vec![
+                    Posting::new(LedgerAccount::ClientAvailable(1), Currency::Eur, 0.0),
+                    Posting::new(LedgerAccount::Fees, Currency::Eur, 1.0),
+                ],
+            )
+            .postings
+            .len()
+        );
+    }
+    #[test]
+    fn test_trial_balance() {
+        let trial_balance = TrialBalance::from_journal(&[
+            deposit_entry(1, 1, 10.0, Currency::Eur),
+            deposit_entry(2, 2, 5.0, Currency::Eur),
+            deposit_entry(3, 1, 2.5, Currency::Usd),
+        ]);
+        assert_eq!(
+            btreemap! {
+                (LedgerAccount::ClientAvailable(1), Currency::Eur) => 10.0,
+                (LedgerAccount::ClientAvailable(1), Currency::Usd) => 2.5,
+                (LedgerAccount::ClientAvailable(2), Currency::Eur) => 5.0,
+                (LedgerAccount::ExternalSettlement, Currency::Eur) => -15.0,
+                (LedgerAccount::ExternalSettlement, Currency::Usd) => -2.5,
+            },
+            trial_balance.balances
+        );
+        assert!(trial_balance.is_balanced());
+    }
+    #[test]
+    fn test_reconcile() {
+        let trial_balance = TrialBalance::from_journal(&[
+            deposit_entry(1, 1, 10.0, Currency::Eur),
+            JournalEntry::new(
+                deposit(2, 2, 5.0),
+                vec![
+                    Posting::new(LedgerAccount::ClientAvailable(2), Currency::Eur, 4.0),
+                    Posting::new(LedgerAccount::Fees, Currency::Eur, 1.0),
+                    Posting::new(LedgerAccount::ExternalSettlement, Currency::Eur, -5.0),
+                ],
+            ),
+        ]);
+        let mut accounts = vec![
+            Account::new(1, Currency::Eur, 10.0, 0.0, false),
+            Account::new(2, Currency::Eur, 4.0, 0.0, false),
+            // the fees are on the house account
+            Account::new(0, Currency::Eur, 1.0, 0.0, false),
+        ];
+        assert_eq!(
+            Vec::<Discrepancy>::new(),
+            trial_balance.reconcile(&accounts, 0)
+        );
+        // the fee credit overwrote the client's own balance
+        accounts[1].available = 1.0;
+        accounts.push(Account::new(3, Currency::Usd, 0.0, 2.0, false));
+        assert_eq!(
+            vec![
+                Discrepancy {
+                    client_id: 2,
+                    currency: Currency::Eur,
+                    journal: (4.0, 0.0),
+                    account: (1.0, 0.0),
+                },
+                Discrepancy {
+                    client_id: 3,
+                    currency: Currency::Usd,
+                    journal: (0.0, 0.0),
+                    account: (0.0, 2.0),
+                },
+            ],
+            trial_balance.reconcile(&accounts, 0)
+        );
+    }
+}
@@ -0,0 +1,273 @@
+use crate::account::Account;
+use crate::client_limits::DailyWithdrawal;
+use crate::currency::Currency;
+use crate::error_budget::Checkpoint;
+use crate::history::HistoryEntry;
+use crate::journal::JournalEntry;
+use crate::lock_policies::RiskCounters;
+use crate::transaction::{ClientId, Transaction, TransactionId};
+use chrono::NaiveDate;
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+// Set of account and transaction writes that must be persisted all together or not at all
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LedgerUpdate {
+    pub accounts: Vec<Account>,
+    pub transactions: Vec<Transaction>,
+    pub history: Vec<HistoryEntry>,
+    pub journal: Vec<JournalEntry>,
+    pub daily_withdrawals: Vec<DailyWithdrawal>,
+    pub risk_counters: Vec<RiskCounters>,
+    // scheduled transactions the update executes, taken off the schedule with it
+    pub unscheduled: Vec<ScheduledTransaction>,
+    // future-dated transactions the update puts on the schedule
+    pub scheduled: Vec<Transaction>,
+}
+
+// A transaction on the schedule, its sequence number tells it apart from an equal one
+// scheduled as well and locates it in the store
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ScheduledTransaction {
+    pub sequence: u64,
+    pub transaction: Transaction,
+}
+
+impl LedgerUpdate {
+    pub fn new(accounts: Vec<Account>, transactions: Vec<Transaction>) -> LedgerUpdate {
+        LedgerUpdate {
+            accounts,
+            transactions,
+            history: vec![],
+            journal: vec![],
+            daily_withdrawals: vec![],
+            risk_counters: vec![],
+            unscheduled: vec![],
+            scheduled: vec![],
+        }
+    }
+}
+
+pub trait LedgerStore: Debug + Send {
+    fn get_account(
+        &self,
+        client_id: ClientId,
+        currency: Currency,
+    ) -> anyhow::Result<Option<Account>>;
+    // all accounts of the client, one per currency it holds
+    fn client_accounts(&self, client_id: ClientId) -> anyhow::Result<Vec<Account>>;
+    fn put_account(&mut self, account: Account) -> anyhow::Result<()>;
+    fn get_transaction(&self, id: TransactionId) -> anyhow::Result<Option<Transaction>>;
+    fn put_transaction(&mut self, transaction: Transaction) -> anyhow::Result<()>;
+    fn apply(&mut self, update: LedgerUpdate) -> anyhow::Result<()>;
+    fn accounts(&self) -> anyhow::Result<Vec<Account>>;
+    fn append_history(&mut self, entry: HistoryEntry) -> anyhow::Result<()>;
+    // entries are returned in the order they were appended
+    fn client_history(&self, client_id: ClientId) -> anyhow::Result<Vec<HistoryEntry>>;
+    // journal entries of all accepted transactions, in the order they were applied
+    fn journal(&self) -> anyhow::Result<Vec<JournalEntry>>;
+    // total withdrawn from the account on the given day, 0 if nothing was withdrawn
+    fn get_daily_withdrawal(
+        &self,
+        client_id: ClientId,
+        currency: Currency,
+        date: NaiveDate,
+    ) -> anyhow::Result<f32>;
+    // dispute counters of the client, one per currency it ever had a dispute in
+    fn client_risk_counters(&self, client_id: ClientId) -> anyhow::Result<Vec<RiskCounters>>;
+    // queues a future-dated transaction until its `effective_at` date
+    fn schedule(&mut self, transaction: Transaction) -> anyhow::Result<()>;
+    // the first scheduled transaction due on the given date, by date and then in the order
+    // they were scheduled. It stays scheduled until an update lists it as unscheduled.
+    fn next_due(&self, on: NaiveDate) -> anyhow::Result<Option<ScheduledTransaction>>;
+    fn scheduled(&self) -> anyhow::Result<Vec<Transaction>>;
+    // where the last run of the input stopped when it was given up
+    fn get_checkpoint(&self, input: &str) -> anyhow::Result<Option<Checkpoint>>;
+    fn put_checkpoint(&mut self, checkpoint: Checkpoint) -> anyhow::Result<()>;
+    fn remove_checkpoint(&mut self, input: &str) -> anyhow::Result<()>;
+    fn flush(&self) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryLedgerStore {
+    account_map: HashMap<ClientId, HashMap<Currency, Account>>,
+    transaction_map: HashMap<TransactionId, Transaction>,
+    history_map: HashMap<ClientId, Vec<HistoryEntry>>,
+    journal: Vec<JournalEntry>,
+    daily_withdrawal_map: HashMap<(ClientId, Currency, NaiveDate), f32>,
+    risk_counter_map: HashMap<ClientId, HashMap<Currency, RiskCounters>>,
+    // kept sorted by effective date, in scheduling order within a date
+    scheduled: Vec<ScheduledTransaction>,
+    next_sequence: u64,
+    checkpoint_map: HashMap<String, Checkpoint>,
+}
+
+impl InMemoryLedgerStore {
+    pub fn new() -> InMemoryLedgerStore {
+        InMemoryLedgerStore::default()
+    }
+}
+
+impl LedgerStore for InMemoryLedgerStore {
+    fn get_account(
+        &self,
+        client_id: ClientId,
+        currency: Currency,
+    ) -> anyhow::Result<Option<Account>> {
+        Ok(self
+            .account_map
+            .get(&client_id)
+            .and_then(|accounts| accounts.get(&currency))
+            .copied())
+    }
+    fn client_accounts(&self, client_id: ClientId) -> anyhow::Result<Vec<Account>> {
+        Ok(self
+            .account_map
+            .get(&client_id)
+            .map(|accounts| accounts.values().copied().collect())
+            .unwrap_or_default())
+    }
+    fn put_account(&mut self, account: Account) -> anyhow::Result<()> {
+        self.account_map
+            .entry(account.client_id)
+            .or_default()
+            .insert(account.currency, account);
+        Ok(())
+    }
+    fn get_transaction(&self, id: TransactionId) -> anyhow::Result<Option<Transaction>> {
+        Ok(self.transaction_map.get(&id).copied())
+    }
+    fn put_transaction(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+        self.transaction_map.insert(transaction.id, transaction);
+        Ok(())
+    }
+    fn apply(&mut self, update: LedgerUpdate) -> anyhow::Result<()> {
+        // inserting into a HashMap can not fail, so the update is trivially atomic
+        for account in update.accounts {
+            self.put_account(account)?;
+        }
+        for transaction in update.transactions {
+            self.transaction_map.insert(transaction.id, transaction);
+        }
+        for entry in update.history {
+            self.append_history(entry)?;
+        }
+        self.journal.extend(update.journal);
+        for daily_withdrawal in update.daily_withdrawals {
+            self.daily_withdrawal_map.insert(
+                (
+                    daily_withdrawal.client_id,
+                    daily_withdrawal.currency,
+                    daily_withdrawal.date,
+                ),
+                daily_withdrawal.amount,
+            );
+        }
+        for risk_counters in update.risk_counters {
+            self.risk_counter_map
+                .entry(risk_counters.client_id)
+                .or_default()
+                .insert(risk_counters.currency, risk_counters);
+        }
+        for unscheduled in update.unscheduled {
+            if let Some(position) = self
+                .scheduled
+                .iter()
+                .position(|scheduled| scheduled.sequence == unscheduled.sequence)
+            {
+                self.scheduled.remove(position);
+            }
+        }
+        for transaction in update.scheduled {
+            self.schedule(transaction)?;
+        }
+        Ok(())
+    }
+    fn accounts(&self) -> anyhow::Result<Vec<Account>> {
+        Ok(self
+            .account_map
+            .values()
+            .flat_map(|accounts| accounts.values().copied())
+            .collect())
+    }
+    fn append_history(&mut self, entry: HistoryEntry) -> anyhow::Result<()> {
+        self.history_map
+            .entry(entry.account.client_id)
+            .or_default()
+            .push(entry);
+        Ok(())
+    }
+    fn client_history(&self, client_id: ClientId) -> anyhow::Result<Vec<HistoryEntry>> {
+        Ok(self
+            .history_map
+            .get(&client_id)
+            .cloned()
+            .unwrap_or_default())
+    }
+    fn journal(&self) -> anyhow::Result<Vec<JournalEntry>> {
+        Ok(self.journal.clone())
+    }
+    fn get_daily_withdrawal(
+        &self,
+        client_id: ClientId,
+        currency: Currency,
+        date: NaiveDate,
+    ) -> anyhow::Result<f32> {
+        Ok(self
+            .daily_withdrawal_map
+            .get(&(client_id, currency, date))
+            .copied()
+            .unwrap_or(0.0))
+    }
+    fn client_risk_counters(&self, client_id: ClientId) -> anyhow::Result<Vec<RiskCounters>> {
+        Ok(self
+            .risk_counter_map
+            .get(&client_id)
+            .map(|risk_counters| risk_counters.values().copied().collect())
+            .unwrap_or_default())
+    }
+    fn schedule(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+        self.scheduled.push(ScheduledTransaction {
+            sequence: self.next_sequence,
+            transaction,
+        });
+        self.next_sequence += 1;
+        // stable, so transactions of the same date keep their order
+        self.scheduled
+            .sort_by_key(|scheduled| scheduled.transaction.effective_at);
+        Ok(())
+    }
+    fn next_due(&self, on: NaiveDate) -> anyhow::Result<Option<ScheduledTransaction>> {
+        Ok(self
+            .scheduled
+            .first()
+            .filter(|scheduled| {
+                scheduled
+                    .transaction
+                    .effective_at
+                    .is_none_or(|date| date <= on)
+            })
+            .copied())
+    }
+    fn scheduled(&self) -> anyhow::Result<Vec<Transaction>> {
+        Ok(self
+            .scheduled
+            .iter()
+            .map(|scheduled| scheduled.transaction)
+            .collect())
+    }
+    fn get_checkpoint(&self, input: &str) -> anyhow::Result<Option<Checkpoint>> {
+        Ok(self.checkpoint_map.get(input).cloned())
+    }
+    fn put_checkpoint(&mut self, checkpoint: Checkpoint) -> anyhow::Result<()> {
+        self.checkpoint_map
+            .insert(checkpoint.input.clone(), checkpoint);
+        Ok(())
+    }
+    fn remove_checkpoint(&mut self, input: &str) -> anyhow::Result<()> {
+        self.checkpoint_map.remove(input);
+        Ok(())
+    }
+}
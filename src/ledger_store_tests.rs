@@ -0,0 +1,447 @@
+#![cfg(test)]
+mod tests {
+    use crate::account::Account;
+    use crate::client_limits::DailyWithdrawal;
+    use crate::currency::Currency;
+    use crate::error_budget::ErrorBudget;
+    use crate::history::HistoryEntry;
+    use crate::journal::{JournalEntry, LedgerAccount, Posting};
+    use crate::ledger_store::{InMemoryLedgerStore, LedgerStore, LedgerUpdate};
+    use crate::lock_policies::RiskCounters;
+    use crate::metrics::Metrics;
+    use crate::sled_ledger_store::{LedgerCopy, SledLedgerStore};
+    use crate::test_helpers::{create_transaction_receiver, deposit, dispute, resolve, withdrawal};
+    use crate::transaction::Transaction;
+    use crate::transaction_processor::TransactionProcessor;
+    use chrono::NaiveDate;
+    use maplit::hashmap;
+
+    fn deposit_journal_entry() -> JournalEntry {
+        JournalEntry::new(
+            deposit(1, 1, 10.0),
+            vec![
+                Posting::new(LedgerAccount::ClientAvailable(1), Currency::Eur, 10.0),
+                Posting::new(LedgerAccount::ExternalSettlement, Currency::Eur, -10.0),
+            ],
+        )
+    }
+    fn check_get_and_put(store: &mut dyn LedgerStore) {
+        assert_eq!(None, store.get_account(1, Currency::Eur).unwrap());
+        assert_eq!(None, store.get_transaction(1).unwrap());
+        store
+            .put_account(Account::new(1, Currency::Eur, 10.0, 0.0, false))
+            .unwrap();
+        store.put_transaction(deposit(1, 1, 10.0)).unwrap();
+        assert_eq!(
+            Some(Account::new(1, Currency::Eur, 10.0, 0.0, false)),
+            store.get_account(1, Currency::Eur).unwrap()
+        );
+        assert_eq!(Some(deposit(1, 1, 10.0)), store.get_transaction(1).unwrap());
+    }
+    fn check_apply(store: &mut dyn LedgerStore) {
+        store
+            .apply(LedgerUpdate {
+                accounts: vec![
+                    Account::new(1, Currency::Eur, 10.0, 0.0, false),
+                    Account::new(2, Currency::Eur, 5.0, 0.0, true),
+                ],
+                transactions: vec![deposit(1, 1, 10.0), deposit(2, 2, 5.0)],
+                history: vec![HistoryEntry::accepted(
+                    deposit(1, 1, 10.0),
+                    Account::new(1, Currency::Eur, 10.0, 0.0, false),
+                )],
+                journal: vec![deposit_journal_entry()],
+                daily_withdrawals: vec![DailyWithdrawal {
+                    client_id: 1,
+                    currency: Currency::Eur,
+                    date: NaiveDate::from_ymd_opt(2024, 1, 2).unwrap(),
+                    amount: 3.0,
+                }],
+                risk_counters: vec![RiskCounters {
+                    open_disputes: 1,
+                    ..RiskCounters::new(1, Currency::Eur)
+                }],
+                unscheduled: vec![],
+                scheduled: vec![],
+            })
+            .unwrap();
+        let mut accounts = store.accounts().unwrap();
+        accounts.sort_by_key(|account| account.client_id);
+        assert_eq!(
+            vec![
+                Account::new(1, Currency::Eur, 10.0, 0.0, false),
+                Account::new(2, Currency::Eur, 5.0, 0.0, true)
+            ],
+            accounts
+        );
+        assert_eq!(Some(deposit(2, 2, 5.0)), store.get_transaction(2).unwrap());
+        assert_eq!(
+            vec![HistoryEntry::accepted(
+                deposit(1, 1, 10.0),
+                Account::new(1, Currency::Eur, 10.0, 0.0, false)
+            )],
+            store.client_history(1).unwrap()
+        );
+        assert_eq!(vec![deposit_journal_entry()], store.journal().unwrap());
+        let date = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        assert_eq!(
+            3.0,
+            store.get_daily_withdrawal(1, Currency::Eur, date).unwrap()
+        );
+        assert_eq!(
+            0.0,
+            store
+                .get_daily_withdrawal(1, Currency::Eur, date.succ_opt().unwrap())
+                .unwrap()
+        );
+        assert_eq!(
+            vec![RiskCounters {
+                open_disputes: 1,
+                ..RiskCounters::new(1, Currency::Eur)
+            }],
+            store.client_risk_counters(1).unwrap()
+        );
+        assert!(store.client_risk_counters(2).unwrap().is_empty());
+    }
+    fn check_history_order(store: &mut dyn LedgerStore) {
+        let entries = vec![
+            HistoryEntry::accepted(
+                deposit(1, 1, 10.0),
+                Account::new(1, Currency::Eur, 10.0, 0.0, false),
+            ),
+            HistoryEntry::rejected(
+                deposit(2, 1, 5.0),
+                Account::new(1, Currency::Eur, 10.0, 0.0, false),
+                "account is locked",
+            ),
+            HistoryEntry::accepted(
+                deposit(3, 1, 1.0),
+                Account::new(1, Currency::Eur, 11.0, 0.0, false),
+            ),
+        ];
+        for entry in &entries {
+            store.append_history(entry.clone()).unwrap();
+        }
+        store
+            .append_history(HistoryEntry::accepted(
+                deposit(4, 2, 1.0),
+                Account::new(2, Currency::Eur, 1.0, 0.0, false),
+            ))
+            .unwrap();
+        assert_eq!(entries, store.client_history(1).unwrap());
+        assert_eq!(Vec::<HistoryEntry>::new(), store.client_history(3).unwrap());
+    }
+    fn scheduled(transaction: Transaction, day: u32) -> Transaction {
+        Transaction {
+            effective_at: NaiveDate::from_ymd_opt(2024, 1, day),
+            ..transaction
+        }
+    }
+    fn check_scheduling(store: &mut dyn LedgerStore) {
+        store.schedule(scheduled(deposit(1, 1, 1.0), 3)).unwrap();
+        store.schedule(scheduled(deposit(2, 1, 1.0), 1)).unwrap();
+        store.schedule(scheduled(deposit(3, 2, 1.0), 3)).unwrap();
+        store.schedule(scheduled(deposit(4, 2, 1.0), 2)).unwrap();
+        // an equal transaction scheduled again is a separate entry
+        store.schedule(scheduled(deposit(4, 2, 1.0), 2)).unwrap();
+        let on = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let next_due = |store: &dyn LedgerStore| {
+            store
+                .next_due(on)
+                .unwrap()
+                .map(|scheduled| scheduled.transaction)
+        };
+        // a due transaction stays scheduled until an update takes it off
+        for _ in 0..2 {
+            assert_eq!(Some(scheduled(deposit(2, 1, 1.0), 1)), next_due(store));
+        }
+        for transaction in [
+            scheduled(deposit(2, 1, 1.0), 1),
+            scheduled(deposit(4, 2, 1.0), 2),
+            scheduled(deposit(4, 2, 1.0), 2),
+        ] {
+            let due = store.next_due(on).unwrap().unwrap();
+            assert_eq!(transaction, due.transaction);
+            store
+                .apply(LedgerUpdate {
+                    unscheduled: vec![due],
+                    ..LedgerUpdate::default()
+                })
+                .unwrap();
+        }
+        assert_eq!(
+            vec![
+                scheduled(deposit(1, 1, 1.0), 3),
+                scheduled(deposit(3, 2, 1.0), 3)
+            ],
+            store.scheduled().unwrap()
+        );
+        assert_eq!(None, next_due(store));
+    }
+    #[test]
+    fn test_in_memory_get_and_put() {
+        check_get_and_put(&mut InMemoryLedgerStore::new());
+    }
+    #[test]
+    fn test_in_memory_apply() {
+        check_apply(&mut InMemoryLedgerStore::new());
+    }
+    #[test]
+    fn test_in_memory_history_order() {
+        check_history_order(&mut InMemoryLedgerStore::new());
+    }
+    #[test]
+    fn test_in_memory_scheduling() {
+        check_scheduling(&mut InMemoryLedgerStore::new());
+    }
+    #[test]
+    fn test_sled_get_and_put() {
+        let dir = tempfile::tempdir().unwrap();
+        check_get_and_put(&mut SledLedgerStore::open(dir.path()).unwrap());
+    }
+    #[test]
+    fn test_sled_apply() {
+        let dir = tempfile::tempdir().unwrap();
+        check_apply(&mut SledLedgerStore::open(dir.path()).unwrap());
+    }
+    #[test]
+    fn test_sled_history_order() {
+        let dir = tempfile::tempdir().unwrap();
+        check_history_order(&mut SledLedgerStore::open(dir.path()).unwrap());
+    }
+    #[test]
+    fn test_sled_scheduling() {
+        let dir = tempfile::tempdir().unwrap();
+        check_scheduling(&mut SledLedgerStore::open(dir.path()).unwrap());
+    }
+    // sled releases its file lock from a background thread shortly after the store is
+    // dropped, so block on the lock before the ledger is opened again
+    fn wait_for_release(path: &std::path::Path) {
+        if let Ok(file) = std::fs::File::open(path.join("db")) {
+            file.lock().unwrap();
+        }
+    }
+    fn files(path: &std::path::Path) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+        let mut found = vec![];
+        for entry in std::fs::read_dir(path).unwrap() {
+            let path = entry.unwrap().path();
+            if path.is_dir() {
+                found.extend(files(&path));
+            } else {
+                found.push((path.clone(), std::fs::read(&path).unwrap()));
+            }
+        }
+        found.sort();
+        found
+    }
+    #[tokio::test]
+    async fn test_sled_scheduled_transactions_survive_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut accounts = vec![];
+        for (day, transactions) in [
+            (
+                1,
+                vec![deposit(1, 1, 10.0), scheduled(deposit(2, 1, 5.0), 3)],
+            ),
+            (2, vec![]),
+            (3, vec![]),
+        ] {
+            wait_for_release(dir.path());
+            accounts.push(
+                TransactionProcessor::with_store(
+                    create_transaction_receiver(transactions),
+                    Box::new(SledLedgerStore::open(dir.path()).unwrap()),
+                )
+                .with_clock(NaiveDate::from_ymd_opt(2024, 1, day).unwrap())
+                .execute()
+                .await
+                .get_account_map(),
+            );
+        }
+        assert_eq!(
+            vec![
+                hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+                hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+                hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 15.0, 0.0, false)},
+            ],
+            accounts
+        );
+    }
+    #[tokio::test]
+    async fn test_sled_state_survives_restart() {
+        let dir = tempfile::tempdir().unwrap();
+        TransactionProcessor::with_store(
+            create_transaction_receiver(vec![deposit(1, 1, 10.0)]),
+            Box::new(SledLedgerStore::open(dir.path()).unwrap()),
+        )
+        .execute()
+        .await;
+        wait_for_release(dir.path());
+        let accounts = TransactionProcessor::with_store(
+            create_transaction_receiver(vec![dispute(1, 1)]),
+            Box::new(SledLedgerStore::open(dir.path()).unwrap()),
+        )
+        .execute()
+        .await
+        .get_account_map();
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 0.0, 10.0, false)},
+            accounts
+        );
+    }
+    #[tokio::test]
+    async fn test_sled_rerun_after_error_budget() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = |withdrawn| {
+            vec![
+                deposit(1, 1, 10.0),
+                deposit(2, 1, 20.0),
+                dispute(1, 1),
+                resolve(1, 1),
+                // still open when the first run is given up
+                dispute(2, 1),
+                withdrawal(3, 1, withdrawn),
+                resolve(2, 1),
+            ]
+        };
+        let mut accounts = vec![];
+        for (transactions, exceeded) in [(input(50.0), true), (input(5.0), false)] {
+            wait_for_release(dir.path());
+            let metrics = Metrics::shared();
+            for _ in 0..transactions.len() {
+                metrics.lock().unwrap().record_row_sent();
+            }
+            let mut processor = TransactionProcessor::with_store(
+                create_transaction_receiver(transactions),
+                Box::new(SledLedgerStore::open(dir.path()).unwrap()),
+            )
+            .with_error_budget(ErrorBudget::Count(0))
+            .with_checkpoint("transactions.csv")
+            .unwrap()
+            .with_metrics(metrics.clone())
+            .unwrap();
+            processor.execute().await;
+            assert_eq!(exceeded, processor.error_budget_exceeded());
+            accounts.push(processor.get_account_map());
+            if !exceeded {
+                // only the fixed withdrawal and the rows the first run did not get to
+                assert_eq!(5, processor.get_run_summary().rows_skipped);
+                assert_eq!(2, processor.get_error_counts().rows);
+                // the skipped rows are off the channel too
+                assert!(metrics
+                    .lock()
+                    .unwrap()
+                    .render()
+                    .lines()
+                    .any(|line| line == "transaction_resolution_channel_backlog 0"));
+                assert_eq!(7, processor.get_store().journal().unwrap().len());
+                // the rejected withdrawal of the first run and every row once
+                assert_eq!(8, processor.get_store().client_history(1).unwrap().len());
+                assert_eq!(
+                    None,
+                    processor
+                        .get_store()
+                        .get_checkpoint("transactions.csv")
+                        .unwrap()
+                );
+            }
+        }
+        assert_eq!(
+            vec![
+                hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 20.0, false)},
+                hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 25.0, 0.0, false)},
+            ],
+            accounts
+        );
+    }
+    #[tokio::test]
+    async fn test_sled_rerun_of_another_input() {
+        let dir = tempfile::tempdir().unwrap();
+        let run = |transactions| {
+            wait_for_release(dir.path());
+            TransactionProcessor::with_store(
+                create_transaction_receiver(transactions),
+                Box::new(SledLedgerStore::open(dir.path()).unwrap()),
+            )
+            .with_error_budget(ErrorBudget::Count(0))
+            .with_checkpoint("transactions.csv")
+            .unwrap()
+        };
+        let mut processor = run(vec![deposit(1, 1, 10.0), withdrawal(2, 1, 50.0)]);
+        processor.execute().await;
+        assert!(processor.error_budget_exceeded());
+        let checkpoint = processor
+            .get_store()
+            .get_checkpoint("transactions.csv")
+            .unwrap();
+        drop(processor);
+        // a changed first row, and an input shorter than the rows the given up run took
+        for transactions in [
+            vec![deposit(3, 1, 10.0), withdrawal(2, 1, 5.0)],
+            vec![deposit(1, 1, 10.0)],
+        ] {
+            let mut processor = run(transactions);
+            processor.execute().await;
+            assert!(processor.get_checkpoint_mismatch().is_some());
+            assert_eq!(0, processor.get_error_counts().rows);
+            assert_eq!(
+                hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+                processor.get_account_map()
+            );
+            assert_eq!(
+                checkpoint,
+                processor
+                    .get_store()
+                    .get_checkpoint("transactions.csv")
+                    .unwrap()
+            );
+        }
+    }
+    #[tokio::test]
+    async fn test_sled_ledger_copy() {
+        let dir = tempfile::tempdir().unwrap();
+        let temp_dir = tempfile::tempdir().unwrap();
+        TransactionProcessor::with_store(
+            create_transaction_receiver(vec![deposit(1, 1, 10.0)]),
+            Box::new(SledLedgerStore::open(dir.path()).unwrap()),
+        )
+        .execute()
+        .await;
+        wait_for_release(dir.path());
+        let before = files(dir.path());
+        let copy = LedgerCopy::new(dir.path(), temp_dir.path()).unwrap();
+        let mut processor = TransactionProcessor::with_store(
+            create_transaction_receiver(vec![deposit(2, 1, 5.0)]),
+            Box::new(copy.open().unwrap()),
+        );
+        processor.execute().await;
+        // history after the copied one
+        assert_eq!(
+            vec![1, 2],
+            processor
+                .get_store()
+                .client_history(1)
+                .unwrap()
+                .iter()
+                .map(|entry| entry.transaction.id)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(2, processor.get_store().journal().unwrap().len());
+        drop(processor);
+        drop(copy);
+        // the copy is removed once dropped
+        assert_eq!(0, std::fs::read_dir(temp_dir.path()).unwrap().count());
+        // the original is not touched, not even rewritten with the same content
+        assert_eq!(before, files(dir.path()));
+        let original = SledLedgerStore::open(dir.path()).unwrap();
+        assert_eq!(
+            Some(Account::new(1, Currency::Eur, 10.0, 0.0, false)),
+            original.get_account(1, Currency::Eur).unwrap()
+        );
+        assert_eq!(None, original.get_transaction(2).unwrap());
+        assert_eq!(1, original.journal().unwrap().len());
+        // a run that has the ledger open may be writing to it
+        assert!(LedgerCopy::new(dir.path(), temp_dir.path()).is_err());
+        assert!(LedgerCopy::new(dir.path().join("missing"), temp_dir.path()).is_err());
+    }
+}
@@ -0,0 +1,121 @@
+use crate::account::Account;
+use crate::client_limits::ClientLimitsTable;
+use crate::currency::Currency;
+use crate::transaction::ClientId;
+use anyhow::anyhow;
+use csv::{ReaderBuilder, Trim};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockPolicy {
+    // at least `threshold` transactions of the client under dispute at once
+    OpenDisputes,
+    // at least `threshold` charged back from one of the client's accounts in total
+    ChargebackAmount,
+    // an account total more than `threshold` below its overdraft limit
+    NegativeBalance,
+}
+
+impl fmt::Display for LockPolicy {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            LockPolicy::OpenDisputes => write!(f, "open_disputes"),
+            LockPolicy::ChargebackAmount => write!(f, "chargeback_amount"),
+            LockPolicy::NegativeBalance => write!(f, "negative_balance"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+struct LockPolicyDTO {
+    policy: LockPolicy,
+    threshold: f32,
+}
+
+// Dispute activity of a client account that the lock policies look at
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RiskCounters {
+    pub client_id: ClientId,
+    pub currency: Currency,
+    pub open_disputes: u32,
+    pub chargeback_amount: f32,
+}
+
+impl RiskCounters {
+    pub fn new(client_id: ClientId, currency: Currency) -> RiskCounters {
+        RiskCounters {
+            client_id,
+            currency,
+            open_disputes: 0,
+            chargeback_amount: 0.0,
+        }
+    }
+}
+
+// Policies that lock a client automatically, checked after each of its partner transactions
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LockPolicies {
+    thresholds: Vec<(LockPolicy, f32)>,
+}
+
+impl LockPolicies {
+    pub fn new() -> LockPolicies {
+        LockPolicies::default()
+    }
+    pub fn load(path: &str) -> anyhow::Result<LockPolicies> {
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+        let mut lock_policies = LockPolicies::new();
+        for result in reader.deserialize() {
+            let dto: LockPolicyDTO = result?;
+            if dto.threshold < 0.0 {
+                return Err(anyhow!(
+                    "Invalid lock policy: {}, threshold must not be negative",
+                    dto.policy
+                ));
+            }
+            // would lock every client on its first transaction
+            if dto.threshold == 0.0 && dto.policy != LockPolicy::NegativeBalance {
+                return Err(anyhow!(
+                    "Invalid lock policy: {}, threshold must be positive",
+                    dto.policy
+                ));
+            }
+            lock_policies.insert(dto.policy, dto.threshold);
+        }
+        Ok(lock_policies)
+    }
+    pub fn insert(&mut self, policy: LockPolicy, threshold: f32) {
+        self.thresholds.push((policy, threshold));
+    }
+    // the first policy, in configuration order, that the client's state triggers
+    pub fn triggered(
+        &self,
+        accounts: &[Account],
+        counters: &[RiskCounters],
+        client_limits: &ClientLimitsTable,
+    ) -> Option<LockPolicy> {
+        self.thresholds
+            .iter()
+            .find(|(policy, threshold)| match policy {
+                LockPolicy::OpenDisputes => {
+                    counters
+                        .iter()
+                        .map(|counters| counters.open_disputes)
+                        .sum::<u32>() as f32
+                        >= *threshold
+                }
+                LockPolicy::ChargebackAmount => counters
+                    .iter()
+                    .any(|counters| counters.chargeback_amount >= *threshold),
+                LockPolicy::NegativeBalance => accounts.iter().any(|account| {
+                    let overdraft_limit = client_limits
+                        .get(account.client_id, account.currency)
+                        .overdraft_limit;
+                    account.available + account.held < -(overdraft_limit + *threshold)
+                }),
+            })
+            .map(|(policy, _)| *policy)
+    }
+}
@@ -0,0 +1,93 @@
+#![cfg(test)]
+mod tests {
+    use crate::account::Account;
+    use crate::client_limits::{ClientLimits, ClientLimitsTable};
+    use crate::currency::Currency;
+    use crate::lock_policies::{LockPolicies, LockPolicy, RiskCounters};
+
+    #[test]
+    fn test_lock_policies() {
+        let lock_policies = LockPolicies::load("test-lock-policies.csv").unwrap();
+        let account = Account::new(1, Currency::Eur, 10.0, 0.0, false);
+        let counters = RiskCounters::new(1, Currency::Eur);
+        let mut client_limits = ClientLimitsTable::new();
+        assert_eq!(
+            None,
+            lock_policies.triggered(&[account], &[counters], &client_limits)
+        );
+        // open disputes add up over the client's currencies
+        assert_eq!(
+            Some(LockPolicy::OpenDisputes),
+            lock_policies.triggered(
+                &[account],
+                &[
+                    RiskCounters {
+                        open_disputes: 1,
+                        ..counters
+                    },
+                    RiskCounters {
+                        open_disputes: 1,
+                        ..RiskCounters::new(1, Currency::Usd)
+                    }
+                ],
+                &client_limits
+            )
+        );
+        assert_eq!(
+            Some(LockPolicy::ChargebackAmount),
+            lock_policies.triggered(
+                &[account],
+                &[RiskCounters {
+                    chargeback_amount: 100.0,
+                    ..counters
+                }],
+                &client_limits
+            )
+        );
+        assert_eq!(
+            Some(LockPolicy::NegativeBalance),
+            lock_policies.triggered(
+                &[Account::new(1, Currency::Eur, -10.0, 5.0, false)],
+                &[counters],
+                &client_limits
+            )
+        );
+        // an account using its overdraft is only negative past the limit
+        client_limits.insert(
+            1,
+            Currency::Eur,
+            ClientLimits {
+                overdraft_limit: 20.0,
+                ..ClientLimits::default()
+            },
+        );
+        assert_eq!(
+            None,
+            lock_policies.triggered(
+                &[Account::new(1, Currency::Eur, -10.0, -10.0, false)],
+                &[counters],
+                &client_limits
+            )
+        );
+        assert_eq!(
+            Some(LockPolicy::NegativeBalance),
+            lock_policies.triggered(
+                &[Account::new(1, Currency::Eur, -25.0, 0.0, false)],
+                &[counters],
+                &client_limits
+            )
+        );
+    }
+    #[test]
+    fn test_no_lock_policies() {
+        let account = Account::new(1, Currency::Eur, -10.0, 0.0, false);
+        assert_eq!(
+            None,
+            LockPolicies::new().triggered(
+                &[account],
+                &[RiskCounters::new(1, Currency::Eur)],
+                &ClientLimitsTable::new()
+            )
+        );
+    }
+}
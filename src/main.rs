@@ -1,30 +1,312 @@
 pub mod account;
+pub mod cli;
+mod cli_tests;
+pub mod client_limits;
+mod client_limits_tests;
+pub mod currency;
+pub mod error_budget;
+mod error_budget_tests;
+pub mod fee_schedule;
+mod fee_schedule_tests;
+pub mod fx_rates;
+mod fx_rates_tests;
+pub mod history;
+pub mod interest;
+mod interest_tests;
+pub mod journal;
+mod journal_tests;
+pub mod ledger_store;
+mod ledger_store_tests;
+pub mod lock_policies;
+mod lock_policies_tests;
+pub mod metrics;
+mod metrics_tests;
+pub mod rules;
+mod rules_tests;
+pub mod run_summary;
+pub mod sled_ledger_store;
+mod test_helpers;
 pub mod transaction;
 pub mod transaction_parser;
 mod transaction_parser_tests;
 pub mod transaction_processor;
 mod transaction_processor_tests;
+pub mod type_aliases;
+mod type_aliases_tests;
 
-use crate::transaction_parser::TransactionParser;
+use crate::cli::{Command, Options, USAGE};
+use crate::client_limits::ClientLimitsTable;
+use crate::fee_schedule::{FeeSchedule, DEFAULT_HOUSE_CLIENT_ID};
+use crate::fx_rates::FxRates;
+use crate::interest::InterestRates;
+use crate::ledger_store::{InMemoryLedgerStore, LedgerStore};
+use crate::lock_policies::LockPolicies;
+use crate::metrics::Metrics;
+use crate::rules::RulesEngine;
+use crate::run_summary::SummaryFormat;
+use crate::sled_ledger_store::{LedgerCopy, SledLedgerStore};
+use crate::transaction_parser::{TransactionParser, TransactionSource, LATEST_SCHEMA_VERSION};
 use crate::transaction_processor::TransactionProcessor;
+use crate::type_aliases::TypeAliases;
+use std::collections::HashMap;
 use std::env;
+use std::process;
+use tokio::net::TcpListener;
 use tokio::sync::mpsc;
 
 #[tokio::main]
 async fn main() {
     let args: Vec<String> = env::args().collect();
-    let input_filename = args[1].clone();
+    let options = match Options::parse(&args[1..]) {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("{}\n{}", e, USAGE);
+            process::exit(2);
+        }
+    };
+    // `--admin` marks the input as coming from the support team, the only source
+    // allowed to lock, unlock and adjust accounts
+    let source = if options.admin {
+        TransactionSource::Admin
+    } else {
+        TransactionSource::Partner
+    };
+    let fx_rates = match &options.fx_rates {
+        Some(path) => load_or_exit(path, FxRates::load(path)),
+        None => FxRates::new(),
+    };
+    let fee_schedule = match &options.fees {
+        Some(path) => load_or_exit(
+            path,
+            FeeSchedule::load(path, options.fee_account.unwrap_or(DEFAULT_HOUSE_CLIENT_ID)),
+        ),
+        None => FeeSchedule::new(),
+    };
+    let client_limits = match &options.limits {
+        Some(path) => load_or_exit(path, ClientLimitsTable::load(path)),
+        None => ClientLimitsTable::new(),
+    };
+    let rules = match &options.rules {
+        Some(path) => load_or_exit(path, RulesEngine::load(path)),
+        None => RulesEngine::new(),
+    };
+    let lock_policies = match &options.lock_policies {
+        Some(path) => load_or_exit(path, LockPolicies::load(path)),
+        None => LockPolicies::new(),
+    };
+    let type_aliases = match &options.type_aliases {
+        Some(path) => load_or_exit(path, TypeAliases::load(path)),
+        None => TypeAliases::new(),
+    };
+    let interest_rates = match &options.command {
+        Command::AccrueInterest {
+            interest_rates,
+            client_tiers,
+        } => load_or_exit(
+            &match client_tiers {
+                Some(client_tiers) => format!("{} or {}", interest_rates, client_tiers),
+                None => interest_rates.clone(),
+            },
+            InterestRates::load(interest_rates, client_tiers.as_deref()),
+        ),
+        _ => InterestRates::new(),
+    };
+
+    // `--metrics <address>` serves Prometheus metrics for as long as the run lasts
+    let metrics = match &options.metrics {
+        Some(address) => {
+            let listener = match TcpListener::bind(address).await {
+                Ok(listener) => listener,
+                Err(e) => {
+                    eprintln!("failed to serve metrics on {}, {}", address, e);
+                    process::exit(2);
+                }
+            };
+            let metrics = Metrics::shared();
+            tokio::spawn(metrics::serve(listener, metrics.clone()));
+            Some(metrics)
+        }
+        None => None,
+    };
+
+    // `validate` runs against a copy of the ledger, it is removed when dropped so every exit
+    // below drops it first
+    let ledger_copy = match (&options.ledger, &options.command) {
+        (Some(path), Command::Validate) => match LedgerCopy::new(path, env::temp_dir()) {
+            Ok(copy) => Some(copy),
+            Err(e) => {
+                eprintln!("{}", e);
+                process::exit(2);
+            }
+        },
+        _ => None,
+    };
+    // `--ledger <dir>` keeps accounts, transactions and history on disk across runs
+    let opened: anyhow::Result<Box<dyn LedgerStore>> = match (&options.ledger, &ledger_copy) {
+        (_, Some(copy)) => copy
+            .open()
+            .map(|store| Box::new(store) as Box<dyn LedgerStore>),
+        (Some(path), None) => {
+            SledLedgerStore::open(path).map(|store| Box::new(store) as Box<dyn LedgerStore>)
+        }
+        (None, None) => Ok(Box::new(InMemoryLedgerStore::new())),
+    };
+    // a ledger another run has open, or one that is corrupt
+    let store = match opened {
+        Ok(store) => store,
+        Err(e) => {
+            eprintln!(
+                "failed to open the ledger {}, {}",
+                options.ledger.as_deref().unwrap_or_default(),
+                e
+            );
+            drop(ledger_copy);
+            process::exit(2);
+        }
+    };
 
     let channel_size = 1000;
     let (sender, receiver) = mpsc::channel(channel_size);
-    tokio::spawn(async move {
-        TransactionParser::new(input_filename, sender)
-            .parse_transactions()
-            .await;
-    });
-
-    TransactionProcessor::new(receiver)
-        .execute()
-        .await
-        .display_accounts()
+    let parsing = match &options.input {
+        Some(input_filename) => {
+            let mut parser =
+                TransactionParser::new_with_source(input_filename.clone(), sender, source)
+                    .with_schema_version(options.schema_version.unwrap_or(LATEST_SCHEMA_VERSION))
+                    .with_dialect(options.dialect)
+                    .with_type_aliases(type_aliases);
+            // the fee account only takes fee income, when there is a fee schedule
+            if let Some(client_id) = fee_schedule.house_client_id {
+                parser = parser.with_house_client_id(client_id);
+            }
+            if let Some(path) = options.dead_letter {
+                parser = parser.with_dead_letter(path);
+            }
+            if options.resubmit {
+                parser = parser.with_resubmission();
+            }
+            if let Some(metrics) = &metrics {
+                parser = parser.with_metrics(metrics.clone());
+            }
+            Some(tokio::spawn(
+                async move { parser.parse_transactions().await },
+            ))
+        }
+        // a statement can be printed from the ledger alone, without new input
+        None => {
+            drop(sender);
+            None
+        }
+    };
+
+    let processor =
+        match TransactionProcessor::with_store(receiver, store).with_fee_schedule(fee_schedule) {
+            Ok(processor) => processor,
+            Err(e) => {
+                eprintln!("{}", e);
+                drop(ledger_copy);
+                process::exit(2);
+            }
+        };
+    let mut processor = processor
+        .with_fx_rates(fx_rates)
+        .with_client_limits(client_limits)
+        .with_rules(rules)
+        .with_lock_policies(lock_policies);
+    if let Some(date) = options.as_of {
+        processor = processor.with_clock(date);
+    }
+    if let Some(error_budget) = options.error_budget {
+        processor = processor.with_error_budget(error_budget);
+    }
+    // a rerun of an input that was given up skips the rows applied before
+    if let Some(input_filename) = &options.input {
+        processor = processor.with_checkpoint(input_filename).unwrap();
+    }
+    if let Some(metrics) = metrics {
+        processor = processor.with_metrics(metrics).unwrap();
+    }
+    // balances before the run, to show what a validation would change
+    let before = match options.command {
+        Command::Validate => processor.get_account_map(),
+        _ => HashMap::new(),
+    };
+    processor.execute().await;
+    if let Some(e) = processor.get_checkpoint_mismatch() {
+        eprintln!("{}", e);
+        drop(processor);
+        if let Some(parsing) = parsing {
+            let _ = parsing.await;
+        }
+        drop(ledger_copy);
+        process::exit(2);
+    }
+    if processor.error_budget_exceeded() {
+        print_summary(&processor, options.summary);
+        eprintln!(
+            "error budget of {} exceeded: {}",
+            options.error_budget.unwrap(),
+            processor.get_error_counts()
+        );
+        // the processor closed the input, the parser stops at its next row and flushes the
+        // dead letters it wrote so far
+        drop(processor);
+        if let Some(parsing) = parsing {
+            if let Err(e) = parsing.await.unwrap() {
+                eprintln!("{}", e);
+            }
+        }
+        drop(ledger_copy);
+        process::exit(1);
+    }
+    // the parser is done once the processor has read all it sent,
+    // an input that can not be read as a whole is not reported on
+    if let Some(parsing) = parsing {
+        if let Err(e) = parsing.await.unwrap() {
+            eprintln!("{}", e);
+            drop(processor);
+            drop(ledger_copy);
+            process::exit(2);
+        }
+    }
+    match options.command {
+        Command::Process => processor.display_accounts(),
+        Command::Validate => processor.display_validation(&before),
+        Command::AccrueInterest { .. } => {
+            processor.accrue_interest(&interest_rates);
+            processor.display_accounts()
+        }
+        Command::Statement { client_id } => processor.display_statement(client_id),
+        Command::TrialBalance => processor.display_trial_balance(),
+    }
+    print_summary(&processor, options.summary);
+    // without `--ledger` nothing keeps the scheduled transactions for a later run
+    if options.ledger.is_none() {
+        let pending = processor.get_store().scheduled().unwrap().len();
+        if pending > 0 {
+            eprintln!(
+                "{} scheduled transactions were not applied and are lost, run with --ledger to keep them",
+                pending
+            );
+        }
+    }
+}
+
+// a configuration file that can not be loaded stops the run before anything is processed
+fn load_or_exit<T>(path: &str, loaded: anyhow::Result<T>) -> T {
+    match loaded {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("failed to load {}, {}", path, e);
+            process::exit(2);
+        }
+    }
+}
+
+fn print_summary(processor: &TransactionProcessor, format: Option<SummaryFormat>) {
+    let summary = processor.get_run_summary();
+    match format {
+        Some(SummaryFormat::Text) => eprintln!("{}", summary),
+        Some(SummaryFormat::Json) => eprintln!("{}", summary.to_json().unwrap()),
+        None => {}
+    }
 }
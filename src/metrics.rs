@@ -0,0 +1,185 @@
+use log::error;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+const NAMESPACE: &str = "transaction_resolution";
+// upper bounds in seconds of the processing latency histogram buckets
+const LATENCY_BUCKETS: [f64; 7] = [0.000_01, 0.000_1, 0.001, 0.01, 0.1, 1.0, 10.0];
+
+pub type SharedMetrics = Arc<Mutex<Metrics>>;
+
+// Metrics of a running engine, rendered in the Prometheus text format
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metrics {
+    // by (type, status, reason)
+    transaction_map: BTreeMap<(String, String, String), u64>,
+    corrupted_rows: u64,
+    // rows the parser sent and the processor received, their difference is the channel backlog
+    rows_sent: u64,
+    rows_received: u64,
+    latency_buckets: [u64; LATENCY_BUCKETS.len()],
+    latency_sum: f64,
+    latency_count: u64,
+    open_disputes: i64,
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        Metrics::default()
+    }
+    pub fn shared() -> SharedMetrics {
+        Arc::new(Mutex::new(Metrics::new()))
+    }
+    pub fn record_transaction(&mut self, of_type: &str, status: &str, reason: Option<&str>) {
+        *self
+            .transaction_map
+            .entry((
+                of_type.to_string(),
+                status.to_string(),
+                reason.unwrap_or_default().to_string(),
+            ))
+            .or_default() += 1;
+    }
+    pub fn record_corrupted_row(&mut self) {
+        self.corrupted_rows += 1;
+    }
+    pub fn record_row_sent(&mut self) {
+        self.rows_sent += 1;
+    }
+    pub fn record_row_received(&mut self) {
+        self.rows_received += 1;
+    }
+    pub fn observe_latency(&mut self, latency: Duration) {
+        let seconds = latency.as_secs_f64();
+        // buckets are cumulative, a latency counts in every bucket it fits
+        for (bucket, bound) in self.latency_buckets.iter_mut().zip(LATENCY_BUCKETS) {
+            if seconds <= bound {
+                *bucket += 1;
+            }
+        }
+        self.latency_sum += seconds;
+        self.latency_count += 1;
+    }
+    pub fn add_open_disputes(&mut self, change: i64) {
+        self.open_disputes += change;
+    }
+    pub fn render(&self) -> String {
+        let mut text = String::new();
+        // writing to a String does not fail
+        self.write(&mut text).unwrap();
+        text
+    }
+    fn write(&self, text: &mut String) -> std::fmt::Result {
+        writeln!(
+            text,
+            "# HELP {0}_transactions_total Transactions processed by type, status and reason.\n\
+             # TYPE {0}_transactions_total counter",
+            NAMESPACE
+        )?;
+        for ((of_type, status, reason), count) in &self.transaction_map {
+            writeln!(
+                text,
+                "{}_transactions_total{{type=\"{}\",status=\"{}\",reason=\"{}\"}} {}",
+                NAMESPACE,
+                escape(of_type),
+                escape(status),
+                escape(reason),
+                count
+            )?;
+        }
+        writeln!(
+            text,
+            "# HELP {0}_corrupted_rows_total Input rows that could not be parsed.\n\
+             # TYPE {0}_corrupted_rows_total counter\n\
+             {0}_corrupted_rows_total {1}",
+            NAMESPACE, self.corrupted_rows
+        )?;
+        writeln!(
+            text,
+            "# HELP {0}_channel_backlog Rows parsed but not processed yet.\n\
+             # TYPE {0}_channel_backlog gauge\n\
+             {0}_channel_backlog {1}",
+            NAMESPACE,
+            self.rows_sent.saturating_sub(self.rows_received)
+        )?;
+        writeln!(
+            text,
+            "# HELP {0}_transaction_processing_seconds Time to process one transaction.\n\
+             # TYPE {0}_transaction_processing_seconds histogram",
+            NAMESPACE
+        )?;
+        for (bucket, bound) in self.latency_buckets.iter().zip(LATENCY_BUCKETS) {
+            writeln!(
+                text,
+                "{}_transaction_processing_seconds_bucket{{le=\"{}\"}} {}",
+                NAMESPACE, bound, bucket
+            )?;
+        }
+        writeln!(
+            text,
+            "{0}_transaction_processing_seconds_bucket{{le=\"+Inf\"}} {1}\n\
+             {0}_transaction_processing_seconds_sum {2}\n\
+             {0}_transaction_processing_seconds_count {1}",
+            NAMESPACE, self.latency_count, self.latency_sum
+        )?;
+        writeln!(
+            text,
+            "# HELP {0}_open_disputes Transactions under dispute.\n\
+             # TYPE {0}_open_disputes gauge\n\
+             {0}_open_disputes {1}",
+            NAMESPACE, self.open_disputes
+        )
+    }
+}
+
+// label values are quoted, so quotes, backslashes and line breaks in them are escaped
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+// Serves the metrics on `GET /metrics` to every connection accepted by the listener
+pub async fn serve(listener: TcpListener, metrics: SharedMetrics) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, _)) => {
+                let metrics = metrics.clone();
+                tokio::spawn(async move {
+                    if let Err(e) = respond(stream, &metrics).await {
+                        error!("failed to serve metrics, {:?}", e);
+                    }
+                });
+            }
+            Err(e) => error!("failed to accept metrics connection, {:?}", e),
+        }
+    }
+}
+
+async fn respond(mut stream: TcpStream, metrics: &SharedMetrics) -> anyhow::Result<()> {
+    // the request line is all that is needed, it comes in the first read
+    let mut request = [0; 1024];
+    let read = stream.read(&mut request).await?;
+    let request = String::from_utf8_lossy(&request[..read]);
+    let response = if request.starts_with("GET /metrics ") {
+        let body = metrics.lock().unwrap().render();
+        format!(
+            "HTTP/1.1 200 OK\r\n\
+             Content-Type: text/plain; version=0.0.4\r\n\
+             Content-Length: {}\r\n\
+             Connection: close\r\n\r\n{}",
+            body.len(),
+            body
+        )
+    } else {
+        "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n".to_string()
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -0,0 +1,64 @@
+#![cfg(test)]
+mod tests {
+    use crate::metrics::{serve, Metrics};
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+
+    #[test]
+    fn test_render() {
+        let mut metrics = Metrics::new();
+        metrics.record_transaction("deposit", "accepted", None);
+        metrics.record_transaction("deposit", "accepted", None);
+        metrics.record_transaction("withdrawal", "rejected", Some("insufficient \"funds\""));
+        metrics.record_corrupted_row();
+        for _ in 0..3 {
+            metrics.record_row_sent();
+        }
+        metrics.record_row_received();
+        metrics.observe_latency(Duration::from_micros(50));
+        metrics.observe_latency(Duration::from_millis(50));
+        metrics.add_open_disputes(2);
+        metrics.add_open_disputes(-1);
+        let text = metrics.render();
+        for line in [
+            "transaction_resolution_transactions_total{type=\"deposit\",status=\"accepted\",reason=\"\"} 2",
+            "transaction_resolution_transactions_total{type=\"withdrawal\",status=\"rejected\",reason=\"insufficient \\\"funds\\\"\"} 1",
+            "transaction_resolution_corrupted_rows_total 1",
+            "transaction_resolution_channel_backlog 2",
+            // buckets are cumulative
+            "transaction_resolution_transaction_processing_seconds_bucket{le=\"0.00001\"} 0",
+            "transaction_resolution_transaction_processing_seconds_bucket{le=\"0.0001\"} 1",
+            "transaction_resolution_transaction_processing_seconds_bucket{le=\"0.1\"} 2",
+            "transaction_resolution_transaction_processing_seconds_bucket{le=\"+Inf\"} 2",
+            "transaction_resolution_transaction_processing_seconds_count 2",
+            "transaction_resolution_open_disputes 1",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {}", line);
+        }
+    }
+    async fn get(address: &str, path: &str) -> String {
+        let mut stream = TcpStream::connect(address).await.unwrap();
+        stream
+            .write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes())
+            .await
+            .unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+    #[tokio::test]
+    async fn test_serve() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let metrics = Metrics::shared();
+        metrics.lock().unwrap().record_corrupted_row();
+        tokio::spawn(serve(listener, metrics.clone()));
+        let response = get(&address, "/metrics").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with(&metrics.lock().unwrap().render()));
+        assert!(get(&address, "/")
+            .await
+            .starts_with("HTTP/1.1 404 Not Found\r\n"));
+    }
+}
@@ -0,0 +1,161 @@
+use crate::transaction::{ClientId, Transaction, TransactionType};
+use anyhow::anyhow;
+use csv::{ReaderBuilder, Trim};
+use serde::Deserialize;
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleKind {
+    // a withdrawal that makes more than `threshold` withdrawals in the client's last `window` rows
+    WithdrawalVelocity,
+    // a withdrawal within `window` rows after a deposit of at least `threshold`
+    WithdrawalAfterLargeDeposit,
+    // a dispute that makes more than `threshold` percent of the client's deposits and
+    // withdrawals disputed, once the client has at least `window` of them
+    DisputeRate,
+}
+
+// Ordered by severity, the most severe decision on a transaction wins
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum RuleAction {
+    // let the transaction through and mark it in the history
+    Flag,
+    // keep the transaction out of the ledger until an admin releases it
+    Hold,
+    Reject,
+}
+
+#[derive(Deserialize, Debug)]
+struct RuleDTO {
+    name: String,
+    rule: RuleKind,
+    action: RuleAction,
+    threshold: f32,
+    window: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Rule {
+    pub name: String,
+    pub kind: RuleKind,
+    pub action: RuleAction,
+    pub threshold: f32,
+    pub window: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuleDecision {
+    pub rule: String,
+    pub action: RuleAction,
+}
+
+// Rows of a client seen so far, as far back as the widest rule window needs
+#[derive(Debug, Clone, Default)]
+struct ClientActivity {
+    recent: VecDeque<(TransactionType, f32)>,
+    deposits_and_withdrawals: usize,
+    disputes: usize,
+}
+
+// Fraud and velocity rules run on every partner transaction before it is executed.
+// The activity they look at is kept for the current run only.
+#[derive(Debug, Clone, Default)]
+pub struct RulesEngine {
+    rules: Vec<Rule>,
+    activity_map: HashMap<ClientId, ClientActivity>,
+}
+
+impl RulesEngine {
+    pub fn new() -> RulesEngine {
+        RulesEngine::default()
+    }
+    pub fn load(path: &str) -> anyhow::Result<RulesEngine> {
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+        let mut rules_engine = RulesEngine::new();
+        for result in reader.deserialize() {
+            let dto: RuleDTO = result?;
+            if dto.threshold < 0.0 {
+                return Err(anyhow!(
+                    "Invalid rule: {}, threshold must not be negative",
+                    dto.name
+                ));
+            }
+            rules_engine.insert(Rule {
+                name: dto.name,
+                kind: dto.rule,
+                action: dto.action,
+                threshold: dto.threshold,
+                window: dto.window,
+            });
+        }
+        Ok(rules_engine)
+    }
+    pub fn insert(&mut self, rule: Rule) {
+        self.rules.push(rule);
+    }
+    // Decisions of every rule the transaction triggers, given the client's earlier rows.
+    // The transaction is then remembered for the rules of the rows that follow it.
+    pub fn evaluate(&mut self, transaction: &Transaction) -> Vec<RuleDecision> {
+        let activity = self.activity_map.entry(transaction.client_id).or_default();
+        let decisions = self
+            .rules
+            .iter()
+            .filter(|rule| RulesEngine::is_triggered(rule, activity, transaction))
+            .map(|rule| RuleDecision {
+                rule: rule.name.clone(),
+                action: rule.action,
+            })
+            .collect();
+        let history_size = self.rules.iter().map(|rule| rule.window).max().unwrap_or(0);
+        activity
+            .recent
+            .push_back((transaction.of_type, transaction.amount));
+        while activity.recent.len() > history_size {
+            activity.recent.pop_front();
+        }
+        match transaction.of_type {
+            TransactionType::Deposit | TransactionType::Withdrawal => {
+                activity.deposits_and_withdrawals += 1
+            }
+            TransactionType::Dispute => activity.disputes += 1,
+            _ => {}
+        }
+        decisions
+    }
+    fn is_triggered(rule: &Rule, activity: &ClientActivity, transaction: &Transaction) -> bool {
+        let recent = activity.recent.iter().rev();
+        match (rule.kind, transaction.of_type) {
+            // the window includes the withdrawal being evaluated
+            (RuleKind::WithdrawalVelocity, of_type) if RulesEngine::is_withdrawal(of_type) => {
+                let withdrawals = recent
+                    .take(rule.window.saturating_sub(1))
+                    .filter(|(of_type, _)| RulesEngine::is_withdrawal(*of_type))
+                    .count();
+                (withdrawals + 1) as f32 > rule.threshold
+            }
+            (RuleKind::WithdrawalAfterLargeDeposit, of_type)
+                if RulesEngine::is_withdrawal(of_type) =>
+            {
+                recent.take(rule.window).any(|(of_type, amount)| {
+                    *of_type == TransactionType::Deposit && *amount >= rule.threshold
+                })
+            }
+            (RuleKind::DisputeRate, TransactionType::Dispute) => {
+                activity.deposits_and_withdrawals >= rule.window.max(1)
+                    && (activity.disputes + 1) as f32 * 100.0
+                        / activity.deposits_and_withdrawals as f32
+                        > rule.threshold
+            }
+            _ => false,
+        }
+    }
+    // a transfer takes funds out of the client just like a withdrawal
+    fn is_withdrawal(of_type: TransactionType) -> bool {
+        matches!(
+            of_type,
+            TransactionType::Withdrawal | TransactionType::Transfer
+        )
+    }
+}
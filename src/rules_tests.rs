@@ -0,0 +1,129 @@
+#![cfg(test)]
+mod tests {
+    use crate::rules::{RuleAction, RuleDecision, RulesEngine};
+    use crate::test_helpers;
+    use crate::transaction::{ClientId, Transaction, TransactionType};
+
+    fn transaction(of_type: TransactionType, client_id: ClientId, amount: f32) -> Transaction {
+        test_helpers::transaction(of_type, 1, client_id, amount)
+    }
+    fn actions(rules_engine: &mut RulesEngine, transaction: Transaction) -> Vec<RuleAction> {
+        rules_engine
+            .evaluate(&transaction)
+            .into_iter()
+            .map(|decision| decision.action)
+            .collect()
+    }
+    #[test]
+    fn test_withdrawal_velocity() {
+        let mut rules_engine = RulesEngine::load("test-rules.csv").unwrap();
+        let withdrawal = transaction(TransactionType::Withdrawal, 1, 10.0);
+        assert!(actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 100.0)
+        )
+        .is_empty());
+        assert!(actions(&mut rules_engine, withdrawal).is_empty());
+        assert!(actions(&mut rules_engine, withdrawal).is_empty());
+        // other clients do not count towards the window
+        assert!(actions(
+            &mut rules_engine,
+            transaction(TransactionType::Withdrawal, 2, 10.0)
+        )
+        .is_empty());
+        assert_eq!(
+            vec![RuleDecision {
+                rule: "withdrawal burst".to_string(),
+                action: RuleAction::Reject,
+            }],
+            rules_engine.evaluate(&withdrawal)
+        );
+        assert!(actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 10.0)
+        )
+        .is_empty());
+        assert!(actions(&mut rules_engine, withdrawal).is_empty());
+    }
+    #[test]
+    fn test_withdrawal_after_large_deposit() {
+        let mut rules_engine = RulesEngine::load("test-rules.csv").unwrap();
+        let withdrawal = transaction(TransactionType::Withdrawal, 1, 10.0);
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 2000.0),
+        );
+        assert_eq!(
+            vec![RuleAction::Hold],
+            actions(&mut rules_engine, withdrawal)
+        );
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 5.0),
+        );
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 5.0),
+        );
+        assert!(actions(&mut rules_engine, withdrawal).is_empty());
+    }
+    #[test]
+    fn test_transfers_count_as_withdrawals() {
+        let mut rules_engine = RulesEngine::load("test-rules.csv").unwrap();
+        let transfer = Transaction {
+            destination_client_id: Some(2),
+            ..transaction(TransactionType::Transfer, 1, 10.0)
+        };
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 2000.0),
+        );
+        assert_eq!(vec![RuleAction::Hold], actions(&mut rules_engine, transfer));
+        assert_eq!(
+            vec![RuleAction::Hold],
+            actions(
+                &mut rules_engine,
+                transaction(TransactionType::Withdrawal, 1, 10.0)
+            )
+        );
+        // the transfer is the third withdrawal in a row
+        assert_eq!(
+            vec![RuleAction::Reject],
+            actions(&mut rules_engine, transfer)
+        );
+    }
+    #[test]
+    fn test_dispute_rate() {
+        let mut rules_engine = RulesEngine::load("test-rules.csv").unwrap();
+        let dispute = transaction(TransactionType::Dispute, 1, 0.0);
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 10.0),
+        );
+        // too few transactions for the rate to mean anything
+        assert!(actions(&mut rules_engine, dispute).is_empty());
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 10.0),
+        );
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 10.0),
+        );
+        actions(
+            &mut rules_engine,
+            transaction(TransactionType::Deposit, 1, 10.0),
+        );
+        // a second dispute on four deposits is a 50% rate
+        assert_eq!(vec![RuleAction::Flag], actions(&mut rules_engine, dispute));
+    }
+    #[test]
+    fn test_no_rules() {
+        let mut rules_engine = RulesEngine::new();
+        assert!(actions(
+            &mut rules_engine,
+            transaction(TransactionType::Withdrawal, 1, 10.0)
+        )
+        .is_empty());
+    }
+}
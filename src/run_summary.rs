@@ -0,0 +1,116 @@
+use crate::currency::Currency;
+use anyhow::anyhow;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SummaryFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for SummaryFormat {
+    type Err = anyhow::Error;
+    fn from_str(value: &str) -> anyhow::Result<SummaryFormat> {
+        match value {
+            "text" => Ok(SummaryFormat::Text),
+            "json" => Ok(SummaryFormat::Json),
+            _ => Err(anyhow!("invalid summary format: {}", value)),
+        }
+    }
+}
+
+// Statistics of one run of the processor
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct RunSummary {
+    pub rows_read: u64,
+    pub rows_parsed: u64,
+    pub rejected_at_parse: u64,
+    // rows a given up run of the same input applied already
+    pub rows_skipped: u64,
+    // by transaction type, a scheduled transaction counts as scheduled and again once it runs
+    pub accepted: BTreeMap<String, u64>,
+    pub rejected: BTreeMap<String, u64>,
+    pub held: BTreeMap<String, u64>,
+    pub scheduled: BTreeMap<String, u64>,
+    pub disputes_opened: u64,
+    pub disputes_resolved: u64,
+    pub disputes_charged_back: u64,
+    pub accounts_created: u64,
+    pub accounts_locked: u64,
+    // money that entered and left the ledger, per account currency
+    pub deposited: BTreeMap<Currency, f64>,
+    pub withdrawn: BTreeMap<Currency, f64>,
+    pub elapsed_seconds: f64,
+    pub rows_per_second: f64,
+}
+
+impl RunSummary {
+    // adds the counts of another summary, the timings are left as they are
+    pub fn add(&mut self, other: &RunSummary) {
+        self.rows_read += other.rows_read;
+        self.rows_parsed += other.rows_parsed;
+        self.rejected_at_parse += other.rejected_at_parse;
+        self.rows_skipped += other.rows_skipped;
+        for (counts, other_counts) in [
+            (&mut self.accepted, &other.accepted),
+            (&mut self.rejected, &other.rejected),
+            (&mut self.held, &other.held),
+            (&mut self.scheduled, &other.scheduled),
+        ] {
+            for (of_type, count) in other_counts {
+                *counts.entry(of_type.clone()).or_default() += count;
+            }
+        }
+        self.disputes_opened += other.disputes_opened;
+        self.disputes_resolved += other.disputes_resolved;
+        self.disputes_charged_back += other.disputes_charged_back;
+        self.accounts_created += other.accounts_created;
+        self.accounts_locked += other.accounts_locked;
+        for (totals, other_totals) in [
+            (&mut self.deposited, &other.deposited),
+            (&mut self.withdrawn, &other.withdrawn),
+        ] {
+            for (currency, amount) in other_totals {
+                *totals.entry(*currency).or_default() += amount;
+            }
+        }
+    }
+    pub fn to_json(&self) -> anyhow::Result<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for RunSummary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "rows read: {}", self.rows_read)?;
+        writeln!(f, "rows parsed: {}", self.rows_parsed)?;
+        writeln!(f, "rejected at parse: {}", self.rejected_at_parse)?;
+        writeln!(f, "rows skipped: {}", self.rows_skipped)?;
+        for (status, counts) in [
+            ("accepted", &self.accepted),
+            ("rejected", &self.rejected),
+            ("held", &self.held),
+            ("scheduled", &self.scheduled),
+        ] {
+            for (of_type, count) in counts {
+                writeln!(f, "{} {}: {}", status, of_type, count)?;
+            }
+        }
+        writeln!(f, "disputes opened: {}", self.disputes_opened)?;
+        writeln!(f, "disputes resolved: {}", self.disputes_resolved)?;
+        writeln!(f, "disputes charged back: {}", self.disputes_charged_back)?;
+        writeln!(f, "accounts created: {}", self.accounts_created)?;
+        writeln!(f, "accounts locked: {}", self.accounts_locked)?;
+        for (currency, amount) in &self.deposited {
+            writeln!(f, "deposited {}: {:.4}", currency, amount)?;
+        }
+        for (currency, amount) in &self.withdrawn {
+            writeln!(f, "withdrawn {}: {:.4}", currency, amount)?;
+        }
+        writeln!(f, "elapsed seconds: {:.3}", self.elapsed_seconds)?;
+        write!(f, "rows per second: {:.0}", self.rows_per_second)
+    }
+}
@@ -0,0 +1,385 @@
+use crate::account::Account;
+use crate::client_limits::DailyWithdrawal;
+use crate::currency::Currency;
+use crate::error_budget::Checkpoint;
+use crate::history::HistoryEntry;
+use crate::journal::JournalEntry;
+use crate::ledger_store::{LedgerStore, LedgerUpdate, ScheduledTransaction};
+use crate::lock_policies::RiskCounters;
+use crate::transaction::{ClientId, Transaction, TransactionId};
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use std::convert::TryInto;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+// keys are prefixed so accounts and transactions can live in a single tree,
+// which lets a multi-key update be applied as one atomic sled batch
+const ACCOUNT_KEY_PREFIX: u8 = b'a';
+const TRANSACTION_KEY_PREFIX: u8 = b't';
+const HISTORY_KEY_PREFIX: u8 = b'h';
+const JOURNAL_KEY_PREFIX: u8 = b'j';
+const DAILY_WITHDRAWAL_KEY_PREFIX: u8 = b'd';
+const RISK_COUNTERS_KEY_PREFIX: u8 = b'r';
+const SCHEDULED_KEY_PREFIX: u8 = b's';
+const CHECKPOINT_KEY_PREFIX: u8 = b'c';
+
+// tells apart the copies a single process makes
+static COPY_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+fn copy_dir(from: &Path, to: &Path) -> anyhow::Result<()> {
+    fs::create_dir_all(to)?;
+    for entry in fs::read_dir(from)? {
+        let entry = entry?;
+        if entry.file_type()?.is_dir() {
+            copy_dir(&entry.path(), &to.join(entry.file_name()))?;
+        } else {
+            fs::copy(entry.path(), to.join(entry.file_name()))?;
+        }
+    }
+    Ok(())
+}
+
+// A copy of a ledger in a temporary directory, nothing done to the copy reaches the original.
+// The directory is removed on drop, so a process must not exit while it is alive.
+#[derive(Debug)]
+pub struct LedgerCopy {
+    path: PathBuf,
+}
+
+impl LedgerCopy {
+    // The files are copied as they are, opening the original with sled would lock it and
+    // could rewrite it
+    pub fn new<P: AsRef<Path>, Q: AsRef<Path>>(
+        original: P,
+        temp_dir: Q,
+    ) -> anyhow::Result<LedgerCopy> {
+        let original = original.as_ref();
+        let lock = File::open(original.join("db"))
+            .map_err(|e| anyhow!("no ledger at {}: {}", original.display(), e))?;
+        // a run that has the ledger open may be writing to it
+        lock.try_lock_shared()
+            .map_err(|_| anyhow!("ledger at {} is in use", original.display()))?;
+        let copy = LedgerCopy {
+            path: temp_dir.as_ref().join(format!(
+                "ledger-copy-{}-{}",
+                process::id(),
+                COPY_COUNT.fetch_add(1, Ordering::Relaxed)
+            )),
+        };
+        copy_dir(original, &copy.path)?;
+        Ok(copy)
+    }
+    // the stores opened on the copy have to be dropped before it
+    pub fn open(&self) -> anyhow::Result<SledLedgerStore> {
+        SledLedgerStore::open(&self.path)
+    }
+}
+
+impl Drop for LedgerCopy {
+    fn drop(&mut self) {
+        // sled closes its files from a background thread shortly after the store is dropped,
+        // its lock is held until then
+        if let Ok(file) = File::open(self.path.join("db")) {
+            let _ = file.lock();
+        }
+        if let Err(e) = fs::remove_dir_all(&self.path) {
+            eprintln!(
+                "failed to remove ledger copy {}, {}",
+                self.path.display(),
+                e
+            );
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct SledLedgerStore {
+    db: sled::Db,
+}
+
+impl SledLedgerStore {
+    pub fn open<P: AsRef<Path>>(path: P) -> anyhow::Result<SledLedgerStore> {
+        Ok(SledLedgerStore {
+            db: sled::open(path)?,
+        })
+    }
+    fn generate_id(&self) -> anyhow::Result<u64> {
+        Ok(self.db.generate_id()?)
+    }
+    fn client_account_prefix(client_id: ClientId) -> Vec<u8> {
+        let mut key = vec![ACCOUNT_KEY_PREFIX];
+        key.extend_from_slice(&client_id.to_be_bytes());
+        key
+    }
+    fn account_key(client_id: ClientId, currency: Currency) -> Vec<u8> {
+        let mut key = SledLedgerStore::client_account_prefix(client_id);
+        key.extend_from_slice(currency.code().as_bytes());
+        key
+    }
+    fn scan_accounts(&self, prefix: Vec<u8>) -> anyhow::Result<Vec<Account>> {
+        let mut accounts = vec![];
+        for entry in self.db.scan_prefix(prefix) {
+            let (_, value) = entry?;
+            accounts.push(serde_json::from_slice(&value)?);
+        }
+        Ok(accounts)
+    }
+    fn client_history_prefix(client_id: ClientId) -> Vec<u8> {
+        let mut key = vec![HISTORY_KEY_PREFIX];
+        key.extend_from_slice(&client_id.to_be_bytes());
+        key
+    }
+    // sled ids are monotonic, so big-endian keys keep a client's history in append order
+    fn history_key(&self, client_id: ClientId) -> anyhow::Result<Vec<u8>> {
+        let mut key = SledLedgerStore::client_history_prefix(client_id);
+        key.extend_from_slice(&self.generate_id()?.to_be_bytes());
+        Ok(key)
+    }
+    fn journal_key(&self) -> anyhow::Result<Vec<u8>> {
+        let mut key = vec![JOURNAL_KEY_PREFIX];
+        key.extend_from_slice(&self.generate_id()?.to_be_bytes());
+        Ok(key)
+    }
+    fn daily_withdrawal_key(client_id: ClientId, currency: Currency, date: NaiveDate) -> Vec<u8> {
+        let mut key = vec![DAILY_WITHDRAWAL_KEY_PREFIX];
+        key.extend_from_slice(&client_id.to_be_bytes());
+        key.extend_from_slice(currency.code().as_bytes());
+        key.extend_from_slice(date.to_string().as_bytes());
+        key
+    }
+    fn client_risk_counters_prefix(client_id: ClientId) -> Vec<u8> {
+        let mut key = vec![RISK_COUNTERS_KEY_PREFIX];
+        key.extend_from_slice(&client_id.to_be_bytes());
+        key
+    }
+    fn risk_counters_key(client_id: ClientId, currency: Currency) -> Vec<u8> {
+        let mut key = SledLedgerStore::client_risk_counters_prefix(client_id);
+        key.extend_from_slice(currency.code().as_bytes());
+        key
+    }
+    // ISO dates sort like the dates themselves, and the sequence, a sled id, keeps the
+    // scheduling order
+    fn scheduled_key(effective_at: Option<NaiveDate>, sequence: u64) -> Vec<u8> {
+        let mut key = vec![SCHEDULED_KEY_PREFIX];
+        if let Some(date) = effective_at {
+            key.extend_from_slice(date.to_string().as_bytes());
+        }
+        key.extend_from_slice(&sequence.to_be_bytes());
+        key
+    }
+    fn checkpoint_key(input: &str) -> Vec<u8> {
+        let mut key = vec![CHECKPOINT_KEY_PREFIX];
+        key.extend_from_slice(input.as_bytes());
+        key
+    }
+    fn transaction_key(id: TransactionId) -> Vec<u8> {
+        let mut key = vec![TRANSACTION_KEY_PREFIX];
+        key.extend_from_slice(&id.to_be_bytes());
+        key
+    }
+}
+
+impl LedgerStore for SledLedgerStore {
+    fn get_account(
+        &self,
+        client_id: ClientId,
+        currency: Currency,
+    ) -> anyhow::Result<Option<Account>> {
+        match self
+            .db
+            .get(SledLedgerStore::account_key(client_id, currency))?
+        {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    fn client_accounts(&self, client_id: ClientId) -> anyhow::Result<Vec<Account>> {
+        self.scan_accounts(SledLedgerStore::client_account_prefix(client_id))
+    }
+    fn put_account(&mut self, account: Account) -> anyhow::Result<()> {
+        self.db.insert(
+            SledLedgerStore::account_key(account.client_id, account.currency),
+            serde_json::to_vec(&account)?,
+        )?;
+        Ok(())
+    }
+    fn get_transaction(&self, id: TransactionId) -> anyhow::Result<Option<Transaction>> {
+        match self.db.get(SledLedgerStore::transaction_key(id))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    fn put_transaction(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+        self.db.insert(
+            SledLedgerStore::transaction_key(transaction.id),
+            serde_json::to_vec(&transaction)?,
+        )?;
+        Ok(())
+    }
+    fn apply(&mut self, update: LedgerUpdate) -> anyhow::Result<()> {
+        let mut batch = sled::Batch::default();
+        for account in &update.accounts {
+            batch.insert(
+                SledLedgerStore::account_key(account.client_id, account.currency),
+                serde_json::to_vec(account)?,
+            );
+        }
+        for transaction in &update.transactions {
+            batch.insert(
+                SledLedgerStore::transaction_key(transaction.id),
+                serde_json::to_vec(transaction)?,
+            );
+        }
+        for entry in &update.history {
+            batch.insert(
+                self.history_key(entry.account.client_id)?,
+                serde_json::to_vec(entry)?,
+            );
+        }
+        for entry in &update.journal {
+            batch.insert(self.journal_key()?, serde_json::to_vec(entry)?);
+        }
+        for daily_withdrawal in &update.daily_withdrawals {
+            batch.insert(
+                SledLedgerStore::daily_withdrawal_key(
+                    daily_withdrawal.client_id,
+                    daily_withdrawal.currency,
+                    daily_withdrawal.date,
+                ),
+                serde_json::to_vec(daily_withdrawal)?,
+            );
+        }
+        for risk_counters in &update.risk_counters {
+            batch.insert(
+                SledLedgerStore::risk_counters_key(risk_counters.client_id, risk_counters.currency),
+                serde_json::to_vec(risk_counters)?,
+            );
+        }
+        for unscheduled in &update.unscheduled {
+            batch.remove(SledLedgerStore::scheduled_key(
+                unscheduled.transaction.effective_at,
+                unscheduled.sequence,
+            ));
+        }
+        for transaction in &update.scheduled {
+            batch.insert(
+                SledLedgerStore::scheduled_key(transaction.effective_at, self.generate_id()?),
+                serde_json::to_vec(transaction)?,
+            );
+        }
+        self.db.apply_batch(batch)?;
+        Ok(())
+    }
+    fn accounts(&self) -> anyhow::Result<Vec<Account>> {
+        self.scan_accounts(vec![ACCOUNT_KEY_PREFIX])
+    }
+    fn append_history(&mut self, entry: HistoryEntry) -> anyhow::Result<()> {
+        self.db.insert(
+            self.history_key(entry.account.client_id)?,
+            serde_json::to_vec(&entry)?,
+        )?;
+        Ok(())
+    }
+    fn client_history(&self, client_id: ClientId) -> anyhow::Result<Vec<HistoryEntry>> {
+        let mut history = vec![];
+        for entry in self
+            .db
+            .scan_prefix(SledLedgerStore::client_history_prefix(client_id))
+        {
+            let (_, value) = entry?;
+            history.push(serde_json::from_slice(&value)?);
+        }
+        Ok(history)
+    }
+    fn journal(&self) -> anyhow::Result<Vec<JournalEntry>> {
+        let mut journal = vec![];
+        for entry in self.db.scan_prefix([JOURNAL_KEY_PREFIX]) {
+            let (_, value) = entry?;
+            journal.push(serde_json::from_slice(&value)?);
+        }
+        Ok(journal)
+    }
+    fn get_daily_withdrawal(
+        &self,
+        client_id: ClientId,
+        currency: Currency,
+        date: NaiveDate,
+    ) -> anyhow::Result<f32> {
+        let key = SledLedgerStore::daily_withdrawal_key(client_id, currency, date);
+        match self.db.get(key)? {
+            Some(value) => Ok(serde_json::from_slice::<DailyWithdrawal>(&value)?.amount),
+            None => Ok(0.0),
+        }
+    }
+    fn client_risk_counters(&self, client_id: ClientId) -> anyhow::Result<Vec<RiskCounters>> {
+        let mut risk_counters = vec![];
+        for entry in self
+            .db
+            .scan_prefix(SledLedgerStore::client_risk_counters_prefix(client_id))
+        {
+            let (_, value) = entry?;
+            risk_counters.push(serde_json::from_slice(&value)?);
+        }
+        Ok(risk_counters)
+    }
+    fn schedule(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+        self.db.insert(
+            SledLedgerStore::scheduled_key(transaction.effective_at, self.generate_id()?),
+            serde_json::to_vec(&transaction)?,
+        )?;
+        Ok(())
+    }
+    fn next_due(&self, on: NaiveDate) -> anyhow::Result<Option<ScheduledTransaction>> {
+        match self.db.scan_prefix([SCHEDULED_KEY_PREFIX]).next() {
+            Some(entry) => {
+                let (key, value) = entry?;
+                let transaction: Transaction = serde_json::from_slice(&value)?;
+                // the key ends with the sequence
+                let sequence = u64::from_be_bytes(key[key.len() - 8..].try_into()?);
+                Ok(Some(ScheduledTransaction {
+                    sequence,
+                    transaction,
+                })
+                .filter(|scheduled| {
+                    scheduled
+                        .transaction
+                        .effective_at
+                        .is_none_or(|date| date <= on)
+                }))
+            }
+            None => Ok(None),
+        }
+    }
+    fn scheduled(&self) -> anyhow::Result<Vec<Transaction>> {
+        let mut scheduled = vec![];
+        for entry in self.db.scan_prefix([SCHEDULED_KEY_PREFIX]) {
+            let (_, value) = entry?;
+            scheduled.push(serde_json::from_slice(&value)?);
+        }
+        Ok(scheduled)
+    }
+    fn get_checkpoint(&self, input: &str) -> anyhow::Result<Option<Checkpoint>> {
+        match self.db.get(SledLedgerStore::checkpoint_key(input))? {
+            Some(value) => Ok(Some(serde_json::from_slice(&value)?)),
+            None => Ok(None),
+        }
+    }
+    fn put_checkpoint(&mut self, checkpoint: Checkpoint) -> anyhow::Result<()> {
+        self.db.insert(
+            SledLedgerStore::checkpoint_key(&checkpoint.input),
+            serde_json::to_vec(&checkpoint)?,
+        )?;
+        Ok(())
+    }
+    fn remove_checkpoint(&mut self, input: &str) -> anyhow::Result<()> {
+        self.db.remove(SledLedgerStore::checkpoint_key(input))?;
+        Ok(())
+    }
+    fn flush(&self) -> anyhow::Result<()> {
+        self.db.flush()?;
+        Ok(())
+    }
+}
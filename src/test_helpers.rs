@@ -0,0 +1,90 @@
+#![cfg(test)]
+// Transactions the tests feed the processor, built with every optional field left out
+use crate::transaction::{ClientId, Transaction, TransactionId, TransactionType};
+use tokio::sync::mpsc;
+
+pub fn create_transaction_receiver(
+    transactions: Vec<Transaction>,
+) -> mpsc::Receiver<anyhow::Result<Transaction>> {
+    let (sender, receiver) = mpsc::channel(1);
+    tokio::spawn(async move {
+        for transaction in transactions {
+            sender.send(Ok(transaction)).await.unwrap();
+        }
+    });
+    receiver
+}
+pub fn transaction(
+    of_type: TransactionType,
+    id: TransactionId,
+    client_id: ClientId,
+    amount: f32,
+) -> Transaction {
+    Transaction {
+        id,
+        of_type,
+        client_id,
+        amount,
+        under_dispute: false,
+        reason_code: None,
+        currency: None,
+        fx_conversion: None,
+        fee: 0.0,
+        destination_client_id: None,
+        effective_at: None,
+        booked_on: None,
+        authorized_on: None,
+    }
+}
+pub fn deposit(id: TransactionId, client_id: ClientId, amount: f32) -> Transaction {
+    transaction(TransactionType::Deposit, id, client_id, amount)
+}
+pub fn withdrawal(id: TransactionId, client_id: ClientId, amount: f32) -> Transaction {
+    transaction(TransactionType::Withdrawal, id, client_id, amount)
+}
+pub fn dispute(id: TransactionId, client_id: ClientId) -> Transaction {
+    transaction(TransactionType::Dispute, id, client_id, 0.0)
+}
+pub fn resolve(id: TransactionId, client_id: ClientId) -> Transaction {
+    transaction(TransactionType::Resolve, id, client_id, 0.0)
+}
+pub fn chargeback(id: TransactionId, client_id: ClientId) -> Transaction {
+    transaction(TransactionType::Chargeback, id, client_id, 0.0)
+}
+pub fn lock(id: TransactionId, client_id: ClientId) -> Transaction {
+    transaction(TransactionType::Lock, id, client_id, 0.0)
+}
+pub fn unlock(id: TransactionId, client_id: ClientId) -> Transaction {
+    transaction(TransactionType::Unlock, id, client_id, 0.0)
+}
+pub fn adjustment(
+    id: TransactionId,
+    client_id: ClientId,
+    amount: f32,
+    reason_code: u16,
+) -> Transaction {
+    Transaction {
+        reason_code: Some(reason_code),
+        ..transaction(TransactionType::Adjustment, id, client_id, amount)
+    }
+}
+pub fn authorize(id: TransactionId, client_id: ClientId, amount: f32) -> Transaction {
+    transaction(TransactionType::Authorize, id, client_id, amount)
+}
+pub fn capture(id: TransactionId, client_id: ClientId) -> Transaction {
+    transaction(TransactionType::Capture, id, client_id, 0.0)
+}
+pub fn void(id: TransactionId, client_id: ClientId) -> Transaction {
+    transaction(TransactionType::Void, id, client_id, 0.0)
+}
+pub fn transfer(
+    id: TransactionId,
+    client_id: ClientId,
+    destination_client_id: ClientId,
+    amount: f32,
+) -> Transaction {
+    Transaction {
+        destination_client_id: Some(destination_client_id),
+        ..transaction(TransactionType::Transfer, id, client_id, amount)
+    }
+}
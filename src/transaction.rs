@@ -1,19 +1,83 @@
-use serde::Deserialize;
+use crate::currency::Currency;
+use anyhow::anyhow;
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+
+// widen these if the client base or transaction volume outgrows them,
+// the parser rejects ids that do not fit with a clear reason
+pub type ClientId = u32;
+pub type TransactionId = u64;
 
 pub const MIN_EXCLUSIVE_TRANSACTION_AMOUNT: f32 = 0.0;
 // probably not reasonable, but I just want to ensure no overflow
 pub const MAX_INCLUSIVE_TRANSACTION_AMOUNT: f32 = 1_000_000_000.0;
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
 pub struct Transaction {
     pub of_type: TransactionType,
-    pub client_id: u16,
-    pub id: u32,
+    pub client_id: ClientId,
+    pub id: TransactionId,
     pub amount: f32,
     pub under_dispute: bool,
+    // only set on adjustments, explains why support credited or debited the account
+    pub reason_code: Option<u16>,
+    // None on deposits, withdrawals and adjustments means the default currency, on
+    // disputes, resolves and chargebacks it means the referenced transaction's currency
+    pub currency: Option<Currency>,
+    // set on withdrawals paid out of an account in another currency
+    pub fx_conversion: Option<FxConversion>,
+    // fee charged on top of a withdrawal or taken out of a deposit, in the account currency
+    pub fee: f32,
+    // client receiving a transfer, `client_id` is the one sending it
+    #[serde(default)]
+    pub destination_client_id: Option<ClientId>,
+    // transactions dated after the processing date are scheduled until that date
+    #[serde(default)]
+    pub effective_at: Option<NaiveDate>,
+    // day the partner booked the row, the processing date moves forward to it
+    #[serde(default)]
+    pub booked_on: Option<NaiveDate>,
+    // set by the processor on authorizations, the day whose withdrawal total they count against
+    #[serde(default)]
+    pub authorized_on: Option<NaiveDate>,
+}
+
+// Conversion applied when a withdrawal is debited from an account in another currency
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq)]
+pub struct FxConversion {
+    pub account_currency: Currency,
+    // amount of `account_currency` per one unit of the transaction's currency
+    pub rate: f32,
+}
+
+impl Transaction {
+    pub fn resolved_currency(&self) -> Currency {
+        self.currency.unwrap_or_default()
+    }
+    // currency of the account the transaction is booked on
+    pub fn account_currency(&self) -> Currency {
+        match self.fx_conversion {
+            Some(conversion) => conversion.account_currency,
+            None => self.resolved_currency(),
+        }
+    }
+    // amount in the currency of the account the transaction is booked on
+    pub fn account_amount(&self) -> f32 {
+        match self.fx_conversion {
+            Some(conversion) => round_amount(self.amount * conversion.rate),
+            None => self.amount,
+        }
+    }
+}
+
+// amounts are kept to the four decimal places they are reported with
+pub fn round_amount(amount: f32) -> f32 {
+    (amount * 10_000.0).round() / 10_000.0
 }
 
-#[derive(Debug, Clone, Copy, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub enum TransactionType {
     #[serde(rename = "deposit")]
     Deposit,
@@ -25,4 +89,94 @@ pub enum TransactionType {
     Resolve,
     #[serde(rename = "chargeback")]
     Chargeback,
+    #[serde(rename = "lock")]
+    Lock,
+    #[serde(rename = "unlock")]
+    Unlock,
+    #[serde(rename = "adjustment")]
+    Adjustment,
+    // reserves funds for a withdrawal that is captured or voided later
+    #[serde(rename = "authorize")]
+    Authorize,
+    #[serde(rename = "capture")]
+    Capture,
+    #[serde(rename = "void")]
+    Void,
+    // moves funds from one client to another in the same currency
+    #[serde(rename = "transfer")]
+    Transfer,
+    // executes a transaction a rule held, without running the rules on it again
+    #[serde(rename = "release")]
+    Release,
+}
+
+impl TransactionType {
+    // administrative transactions may only come from the admin source and are
+    // allowed on locked accounts
+    pub fn is_admin(&self) -> bool {
+        matches!(
+            self,
+            TransactionType::Lock
+                | TransactionType::Unlock
+                | TransactionType::Adjustment
+                | TransactionType::Release
+        )
+    }
+    // the tx id of these names the transaction itself, the others name the one they act on
+    pub fn has_own_id(&self) -> bool {
+        !matches!(
+            self,
+            TransactionType::Dispute
+                | TransactionType::Resolve
+                | TransactionType::Chargeback
+                | TransactionType::Capture
+                | TransactionType::Void
+                | TransactionType::Release
+        )
+    }
+}
+
+impl fmt::Display for TransactionType {
+    // same names as in the input files
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            TransactionType::Deposit => "deposit",
+            TransactionType::Withdrawal => "withdrawal",
+            TransactionType::Dispute => "dispute",
+            TransactionType::Resolve => "resolve",
+            TransactionType::Chargeback => "chargeback",
+            TransactionType::Lock => "lock",
+            TransactionType::Unlock => "unlock",
+            TransactionType::Adjustment => "adjustment",
+            TransactionType::Authorize => "authorize",
+            TransactionType::Capture => "capture",
+            TransactionType::Void => "void",
+            TransactionType::Transfer => "transfer",
+            TransactionType::Release => "release",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for TransactionType {
+    type Err = anyhow::Error;
+    // the names `Display` writes, in any case
+    fn from_str(name: &str) -> anyhow::Result<TransactionType> {
+        match name.to_lowercase().as_str() {
+            "deposit" => Ok(TransactionType::Deposit),
+            "withdrawal" => Ok(TransactionType::Withdrawal),
+            "dispute" => Ok(TransactionType::Dispute),
+            "resolve" => Ok(TransactionType::Resolve),
+            "chargeback" => Ok(TransactionType::Chargeback),
+            "lock" => Ok(TransactionType::Lock),
+            "unlock" => Ok(TransactionType::Unlock),
+            "adjustment" => Ok(TransactionType::Adjustment),
+            "authorize" => Ok(TransactionType::Authorize),
+            "capture" => Ok(TransactionType::Capture),
+            "void" => Ok(TransactionType::Void),
+            "transfer" => Ok(TransactionType::Transfer),
+            "release" => Ok(TransactionType::Release),
+            _ => Err(anyhow!("unknown transaction type {}", name)),
+        }
+    }
 }
@@ -1,37 +1,291 @@
+use crate::currency::Currency;
+use crate::interest::{is_interest_transaction_id, INTEREST_TRANSACTION_ID_BASE};
+use crate::metrics::SharedMetrics;
 use crate::transaction::{
-    Transaction, TransactionType, MAX_INCLUSIVE_TRANSACTION_AMOUNT,
+    ClientId, Transaction, TransactionId, TransactionType, MAX_INCLUSIVE_TRANSACTION_AMOUNT,
     MIN_EXCLUSIVE_TRANSACTION_AMOUNT,
 };
+use crate::type_aliases::TypeAliases;
 use anyhow::anyhow;
-use csv::{ReaderBuilder, Trim};
+use chrono::NaiveDate;
+use csv::{ByteRecord, Position, ReaderBuilder, StringRecord, Terminator, Trim, WriterBuilder};
+use csv_core::ReadFieldResult;
+use flate2::read::MultiGzDecoder;
 use log::error;
 use serde::Deserialize;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+use std::num::{IntErrorKind, ParseIntError};
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
 use tokio::sync::mpsc;
 
+// the schema version of inputs that do not declare one
+pub const LATEST_SCHEMA_VERSION: u32 = 3;
+
+// The columns an input of a schema version has, in any order
+struct Schema {
+    version: u32,
+    required: &'static [&'static str],
+    optional: &'static [&'static str],
+}
+
+const SCHEMAS: [Schema; 3] = [
+    Schema {
+        version: 1,
+        required: &["type", "client", "tx", "amount"],
+        optional: &["reason", "currency"],
+    },
+    // transfers and scheduled transactions
+    Schema {
+        version: 2,
+        required: &["type", "client", "tx", "amount"],
+        optional: &["reason", "currency", "destination", "effective_at"],
+    },
+    // the booking date of the row, which drives the processing date
+    Schema {
+        version: 3,
+        required: &["type", "client", "tx", "amount"],
+        optional: &["reason", "currency", "destination", "effective_at", "date"],
+    },
+];
+
+// other names partners use for a column
+const COLUMN_ALIASES: [(&str, &str); 5] = [
+    ("client_id", "client"),
+    ("tx_id", "tx"),
+    ("transaction_id", "tx"),
+    ("reason_code", "reason"),
+    ("destination_client", "destination"),
+];
+
+// added to the rows of a dead-letter file, and dropped when it is resubmitted
+const DEAD_LETTER_COLUMNS: [&str; 2] = ["line", "error"];
+
+impl Schema {
+    // the order of the columns in an input without a header
+    fn positional_header(&self) -> StringRecord {
+        self.required.iter().chain(self.optional.iter()).collect()
+    }
+    fn of_version(version: u32) -> anyhow::Result<&'static Schema> {
+        SCHEMAS
+            .iter()
+            .find(|schema| schema.version == version)
+            .ok_or_else(|| anyhow!("Invalid header: unsupported schema version {}", version))
+    }
+    // The header with aliases replaced by the column they stand for. Fails on the first
+    // unknown or duplicate column, or on all missing required columns at once.
+    fn canonical_header(&self, header: &StringRecord) -> anyhow::Result<StringRecord> {
+        let mut columns: Vec<&str> = vec![];
+        for column in header.iter() {
+            let canonical = COLUMN_ALIASES
+                .iter()
+                .find(|(alias, _)| *alias == column)
+                .map_or(column, |(_, canonical)| *canonical);
+            if !self.required.contains(&canonical) && !self.optional.contains(&canonical) {
+                return Err(anyhow!(
+                    "Invalid header: unknown column {} in schema version {}",
+                    column,
+                    self.version
+                ));
+            }
+            if columns.contains(&canonical) {
+                return Err(anyhow!("Invalid header: duplicate column {}", canonical));
+            }
+            columns.push(canonical);
+        }
+        let missing: Vec<&str> = self
+            .required
+            .iter()
+            .filter(|column| !columns.contains(column))
+            .copied()
+            .collect();
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Invalid header: missing column {} in schema version {}",
+                missing.join(", "),
+                self.version
+            ));
+        }
+        Ok(StringRecord::from(columns))
+    }
+}
+
+const UTF8_BOM: &[u8] = &[0xef, 0xbb, 0xbf];
+const GZIP_MAGIC: &[u8] = &[0x1f, 0x8b];
+const ZSTD_MAGIC: &[u8] = &[0x28, 0xb5, 0x2f, 0xfd];
+
+// The input file, decompressed on the fly when it is gzip or zstd compressed, going by
+// its magic bytes or its extension
+fn open_input(path: &str) -> anyhow::Result<Box<dyn Read + Send>> {
+    let mut file = BufReader::new(File::open(path)?);
+    let magic = file.fill_buf()?;
+    if magic.starts_with(GZIP_MAGIC) || path.ends_with(".gz") {
+        // daily files may be several gzip members concatenated
+        Ok(Box::new(MultiGzDecoder::new(file)))
+    } else if magic.starts_with(ZSTD_MAGIC) || path.ends_with(".zst") {
+        Ok(Box::new(zstd::Decoder::with_buffer(file)?))
+    } else {
+        Ok(Box::new(file))
+    }
+}
+
+// Input bytes the csv reader has consumed but the parser has not passed yet, so a row can
+// be written to the dead letters exactly as it is in the input
+#[derive(Debug, Default)]
+struct RawBytes {
+    // offset of the first kept byte in the input
+    offset: u64,
+    bytes: Vec<u8>,
+}
+
+impl RawBytes {
+    // the input bytes from `start` up to `end`, everything before `end` is dropped
+    fn take(&mut self, start: u64, end: u64) -> Vec<u8> {
+        let len = self.bytes.len();
+        let end = (end.saturating_sub(self.offset) as usize).min(len);
+        let start = (start.saturating_sub(self.offset) as usize).min(end);
+        let taken = self.bytes[start..end].to_vec();
+        self.bytes.drain(..end);
+        self.offset += end as u64;
+        taken
+    }
+}
+
+// the input, keeping what is read from it in the shared raw bytes
+struct RawInput {
+    input: Box<dyn Read + Send>,
+    raw_bytes: Arc<Mutex<RawBytes>>,
+}
+
+impl Read for RawInput {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = self.input.read(buf)?;
+        self.raw_bytes
+            .lock()
+            .unwrap()
+            .bytes
+            .extend_from_slice(&buf[..read]);
+        Ok(read)
+    }
+}
+
+// How an input file is written, a UTF-8 byte order mark is always skipped
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CsvDialect {
+    pub delimiter: u8,
+    pub quote: u8,
+    // lines starting with it are skipped
+    pub comment: Option<u8>,
+    // without a header the columns are in schema order, trailing optional ones may be left out
+    pub has_header: bool,
+}
+
+impl Default for CsvDialect {
+    fn default() -> CsvDialect {
+        CsvDialect {
+            delimiter: b',',
+            quote: b'"',
+            comment: None,
+            has_header: true,
+        }
+    }
+}
+
 #[derive(Deserialize, Debug)]
 struct TransactionDTO {
+    // parsed by hand to accept any case and the partner's aliases
     #[serde(rename = "type")]
-    of_type: TransactionType,
-    client: u16,
-    tx: u32,
+    of_type: String,
+    // ids are parsed by hand so an id that does not fit gets a clear rejection reason
+    client: String,
+    tx: String,
     amount: String,
+    #[serde(default)]
+    reason: Option<u16>,
+    #[serde(default)]
+    currency: Option<Currency>,
+    #[serde(default)]
+    destination: Option<String>,
+    #[serde(default)]
+    effective_at: Option<NaiveDate>,
+    #[serde(default)]
+    date: Option<NaiveDate>,
 }
 
 impl TransactionDTO {
-    pub fn to_transaction(&self) -> Transaction {
-        Transaction {
-            of_type: self.of_type,
-            client_id: self.client,
-            id: self.tx,
+    pub fn to_transaction(&self, type_aliases: &TypeAliases) -> anyhow::Result<Transaction> {
+        Ok(Transaction {
+            of_type: type_aliases.parse(&self.of_type)?,
+            client_id: parse_id::<ClientId>("client", &self.client, ClientId::MAX)?,
+            // the ids above the range partners can use are interest, which they can only reference
+            id: parse_id::<TransactionId>("tx", &self.tx, INTEREST_TRANSACTION_ID_BASE - 1)?,
             amount: self.amount.parse::<f32>().unwrap_or(0.0),
             under_dispute: false,
-        }
+            reason_code: self.reason,
+            currency: self.currency,
+            fx_conversion: None,
+            fee: 0.0,
+            destination_client_id: match &self.destination {
+                Some(destination) => Some(parse_id::<ClientId>(
+                    "destination",
+                    destination,
+                    ClientId::MAX,
+                )?),
+                None => None,
+            },
+            effective_at: self.effective_at,
+            booked_on: self.date,
+            authorized_on: None,
+        })
     }
 }
 
+fn parse_id<T>(column: &str, value: &str, max: T) -> anyhow::Result<T>
+where
+    T: FromStr<Err = ParseIntError> + Display,
+{
+    value.parse::<T>().map_err(|e| match e.kind() {
+        IntErrorKind::Empty => anyhow!("Invalid transaction: missing {} id", column),
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => anyhow!(
+            "Invalid transaction: {} id {} is out of range 0..={}",
+            column,
+            value,
+            max
+        ),
+        _ if value.starts_with('-') => anyhow!(
+            "Invalid transaction: {} id {} is out of range 0..={}",
+            column,
+            value,
+            max
+        ),
+        _ => anyhow!(
+            "Invalid transaction: {} id {} is not a number",
+            column,
+            value
+        ),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TransactionSource {
+    Partner,
+    Admin,
+}
+
 pub struct TransactionParser {
     path: String,
     sender: mpsc::Sender<anyhow::Result<Transaction>>,
+    source: TransactionSource,
+    schema_version: u32,
+    dialect: CsvDialect,
+    type_aliases: TypeAliases,
+    dead_letter_path: Option<String>,
+    // the input is a dead-letter file, its rows end with the line and error columns
+    resubmission: bool,
+    house_client_id: Option<ClientId>,
+    metrics: Option<SharedMetrics>,
 }
 
 impl TransactionParser {
@@ -39,39 +293,352 @@ impl TransactionParser {
         path: String,
         sender: mpsc::Sender<anyhow::Result<Transaction>>,
     ) -> TransactionParser {
-        TransactionParser { path, sender }
+        TransactionParser::new_with_source(path, sender, TransactionSource::Partner)
+    }
+    pub fn new_with_source(
+        path: String,
+        sender: mpsc::Sender<anyhow::Result<Transaction>>,
+        source: TransactionSource,
+    ) -> TransactionParser {
+        TransactionParser {
+            path,
+            sender,
+            source,
+            schema_version: LATEST_SCHEMA_VERSION,
+            dialect: CsvDialect::default(),
+            type_aliases: TypeAliases::new(),
+            dead_letter_path: None,
+            resubmission: false,
+            house_client_id: None,
+            metrics: None,
+        }
+    }
+    pub fn with_schema_version(mut self, schema_version: u32) -> TransactionParser {
+        self.schema_version = schema_version;
+        self
+    }
+    pub fn with_dialect(mut self, dialect: CsvDialect) -> TransactionParser {
+        self.dialect = dialect;
+        self
+    }
+    pub fn with_type_aliases(mut self, type_aliases: TypeAliases) -> TransactionParser {
+        self.type_aliases = type_aliases;
+        self
+    }
+    // fees are credited to this client, so rows of it would mix with the fee income
+    pub fn with_house_client_id(mut self, client_id: ClientId) -> TransactionParser {
+        self.house_client_id = Some(client_id);
+        self
+    }
+    pub fn with_metrics(mut self, metrics: SharedMetrics) -> TransactionParser {
+        self.metrics = Some(metrics);
+        self
+    }
+    // rows that can not be parsed are written to this file, in the dialect of the input
+    pub fn with_dead_letter(mut self, path: String) -> TransactionParser {
+        self.dead_letter_path = Some(path);
+        self
     }
-    pub async fn parse_transactions(&self) {
+    // the input is a dead-letter file submitted again, its line and error columns are dropped
+    pub fn with_resubmission(mut self) -> TransactionParser {
+        self.resubmission = true;
+        self
+    }
+    // Fails before sending any transaction when the input can not be read or its header
+    // does not match the schema; a malformed row only rejects that row.
+    pub async fn parse_transactions(&self) -> anyhow::Result<()> {
+        let schema = Schema::of_version(self.schema_version)?;
+        let raw_bytes = Arc::new(Mutex::new(RawBytes::default()));
         let mut reader = ReaderBuilder::new()
-            .flexible(true)
             .trim(Trim::All)
-            .from_path(&self.path)
-            .unwrap();
-        for result in reader.deserialize() {
-            let transaction = self.parse_transaction(result);
+            .delimiter(self.dialect.delimiter)
+            .quote(self.dialect.quote)
+            .comment(self.dialect.comment)
+            .has_headers(self.dialect.has_header)
+            // field counts are checked here, so that such rows still reach the dead letters
+            .flexible(true)
+            .from_reader(RawInput {
+                input: open_input(&self.path)?,
+                raw_bytes: raw_bytes.clone(),
+            });
+        let (header, raw_header) = if self.dialect.has_header {
+            let input_header = self.without_dead_letter_columns(reader.headers()?)?;
+            let raw_header = raw_bytes.lock().unwrap().take(0, reader.position().byte());
+            (schema.canonical_header(&input_header)?, Some(raw_header))
+        } else {
+            (schema.positional_header(), None)
+        };
+        let mut dead_letter = match &self.dead_letter_path {
+            Some(path) => Some(self.create_dead_letter(path, raw_header.as_deref())?),
+            None => None,
+        };
+        let mut record = ByteRecord::new();
+        loop {
+            let (transaction, raw_row, failed_reading) = match reader.read_byte_record(&mut record)
+            {
+                Ok(false) => break,
+                Ok(true) => {
+                    // the reader leaves the first record untrimmed when there is no header
+                    record.trim();
+                    let transaction = StringRecord::from_byte_record(record.clone())
+                        .map_err(|e| anyhow!("Invalid transaction: {}", e))
+                        .and_then(|record| {
+                            let record = if self.resubmission {
+                                record.iter().take(record.len().saturating_sub(2)).collect()
+                            } else {
+                                record
+                            };
+                            self.parse_transaction(self.deserialize(&record, &header, schema))
+                        });
+                    (transaction, reader.position().byte(), false)
+                }
+                // the rest of the input can not be read, it all goes to the dead letters
+                Err(e) => (Err(anyhow!("Invalid transaction: {}", e)), u64::MAX, true),
+            };
+            let position = record.position().cloned().unwrap_or_else(Position::new);
+            let raw_row = raw_bytes.lock().unwrap().take(position.byte(), raw_row);
+            if let (Err(e), Some(dead_letter)) = (&transaction, dead_letter.as_mut()) {
+                self.write_dead_letter(dead_letter, &raw_row, position.line(), e)?;
+            }
+            // the processor closes the input once it gives the run up
             if let Err(e) = self.sender.send(transaction).await {
                 error!("Failed to send transaction - {:?}", e.to_string());
-                return;
+                break;
+            }
+            if let Some(metrics) = &self.metrics {
+                metrics.lock().unwrap().record_row_sent();
+            }
+            if failed_reading {
+                break;
+            }
+        }
+        if let Some(mut dead_letter) = dead_letter {
+            dead_letter.flush()?;
+        }
+        Ok(())
+    }
+    // The header without the line and error columns of a resubmitted dead-letter file
+    fn without_dead_letter_columns(&self, header: &StringRecord) -> anyhow::Result<StringRecord> {
+        if !self.resubmission {
+            return Ok(header.clone());
+        }
+        let columns = header.len().saturating_sub(DEAD_LETTER_COLUMNS.len());
+        if header.iter().skip(columns).ne(DEAD_LETTER_COLUMNS) {
+            return Err(anyhow!(
+                "Invalid header: a resubmitted dead-letter file ends with the columns {}",
+                DEAD_LETTER_COLUMNS.join(", ")
+            ));
+        }
+        Ok(header.iter().take(columns).collect())
+    }
+    fn deserialize(
+        &self,
+        record: &StringRecord,
+        header: &StringRecord,
+        schema: &Schema,
+    ) -> anyhow::Result<TransactionDTO> {
+        // trailing optional columns may be left out without a header
+        if record.len() > header.len() || (self.dialect.has_header && record.len() < header.len()) {
+            return Err(anyhow!(
+                "Invalid transaction: {} fields, schema version {} has {} columns",
+                record.len(),
+                schema.version,
+                header.len()
+            ));
+        }
+        Ok(record.deserialize(Some(header))?)
+    }
+    // The dead letters have the header of the input, if it has one, so they can be fixed and
+    // resubmitted as they are, followed by the line of the row in the input and the error.
+    fn create_dead_letter(
+        &self,
+        path: &str,
+        raw_header: Option<&[u8]>,
+    ) -> anyhow::Result<BufWriter<File>> {
+        let mut writer = BufWriter::new(File::create(path)?);
+        if let Some(raw_header) = raw_header {
+            let (_, header, terminator) = self.split_raw_row(raw_header);
+            writer.write_all(header)?;
+            writer.write_all(&self.dead_letter_fields(DEAD_LETTER_COLUMNS)?)?;
+            writer.write_all(terminator)?;
+        }
+        Ok(writer)
+    }
+    // writes the row as it is in the input, followed by its line and the error
+    fn write_dead_letter(
+        &self,
+        writer: &mut BufWriter<File>,
+        raw_row: &[u8],
+        line: u64,
+        e: &anyhow::Error,
+    ) -> anyhow::Result<()> {
+        let (skipped_lines, row, terminator) = self.split_raw_row(raw_row);
+        writer.write_all(row)?;
+        writer.write_all(
+            &self.dead_letter_fields([&(line + skipped_lines).to_string(), &e.to_string()])?,
+        )?;
+        writer.write_all(terminator)?;
+        Ok(())
+    }
+    // The bytes of a row split into the number of blank and comment lines the reader skipped
+    // before it, the row itself and its line terminator. The line and error columns of a
+    // resubmitted row are left out.
+    fn split_raw_row<'a>(&self, raw_row: &'a [u8]) -> (u64, &'a [u8], &'a [u8]) {
+        let mut row = raw_row.strip_prefix(UTF8_BOM).unwrap_or(raw_row);
+        let mut skipped_lines = 0;
+        while let Some(first) = row.first() {
+            if *first != b'\n' && *first != b'\r' && Some(*first) != self.dialect.comment {
+                break;
+            }
+            let line_end = row
+                .iter()
+                .position(|byte| *byte == b'\n')
+                .map_or(row.len(), |i| i + 1);
+            row = &row[line_end..];
+            skipped_lines += 1;
+        }
+        let terminator_len = row
+            .iter()
+            .rev()
+            .take_while(|byte| **byte == b'\n' || **byte == b'\r')
+            .count();
+        let (mut row, terminator) = row.split_at(row.len() - terminator_len);
+        if self.resubmission {
+            row = self.without_dead_letter_fields(row);
+        }
+        // the reader ends a CRLF row at the CR and skips the LF before the next one, and the
+        // last row of an input may end without a terminator
+        let terminator: &[u8] = match terminator {
+            b"" => b"\n",
+            b"\r" => b"\r\n",
+            terminator => terminator,
+        };
+        (skipped_lines, row, terminator)
+    }
+    // the row up to the delimiter before its last two fields, read in the dialect of the input
+    fn without_dead_letter_fields<'a>(&self, row: &'a [u8]) -> &'a [u8] {
+        let mut reader = csv_core::ReaderBuilder::new()
+            .delimiter(self.dialect.delimiter)
+            .quote(self.dialect.quote)
+            .build();
+        let mut field_starts = vec![0];
+        let mut read = 0;
+        // only where the fields start matters, not their content
+        let mut field = [0; 1024];
+        loop {
+            let (result, read_now, _) = reader.read_field(&row[read..], &mut field);
+            read += read_now;
+            match result {
+                ReadFieldResult::Field { record_end: false } => field_starts.push(read),
+                ReadFieldResult::Field { record_end: true } | ReadFieldResult::End => break,
+                ReadFieldResult::InputEmpty | ReadFieldResult::OutputFull => {}
             }
         }
+        match field_starts.len().checked_sub(DEAD_LETTER_COLUMNS.len()) {
+            Some(0) => &row[..0],
+            Some(fields) => &row[..field_starts[fields] - 1],
+            None => row,
+        }
+    }
+    // the fields csv encoded in the dialect of the input, each after a delimiter
+    fn dead_letter_fields(&self, fields: [&str; 2]) -> anyhow::Result<Vec<u8>> {
+        let mut writer = WriterBuilder::new()
+            .delimiter(self.dialect.delimiter)
+            .quote(self.dialect.quote)
+            .terminator(Terminator::Any(b'\n'))
+            .from_writer(vec![self.dialect.delimiter]);
+        writer.write_record(fields)?;
+        let mut encoded = writer.into_inner().map_err(|e| anyhow!("{}", e))?;
+        encoded.pop();
+        Ok(encoded)
     }
     fn parse_transaction(
         &self,
-        parser_result: Result<TransactionDTO, csv::Error>,
+        parser_result: anyhow::Result<TransactionDTO>,
     ) -> anyhow::Result<Transaction> {
         let transaction_dto: TransactionDTO = parser_result?;
-        let transaction = transaction_dto.to_transaction();
-        if transaction.of_type == TransactionType::Deposit
+        let transaction = transaction_dto.to_transaction(&self.type_aliases)?;
+        if (transaction.of_type == TransactionType::Deposit
             || transaction.of_type == TransactionType::Withdrawal
+            || transaction.of_type == TransactionType::Authorize
+            || transaction.of_type == TransactionType::Transfer)
+            && (transaction.amount <= MIN_EXCLUSIVE_TRANSACTION_AMOUNT
+                || transaction.amount > MAX_INCLUSIVE_TRANSACTION_AMOUNT)
         {
-            if transaction.amount <= MIN_EXCLUSIVE_TRANSACTION_AMOUNT
-                || transaction.amount > MAX_INCLUSIVE_TRANSACTION_AMOUNT
+            return Err(anyhow!(
+                "Invalid transaction: {}, amount out of bounds",
+                transaction.id
+            ));
+        }
+        if transaction.of_type.is_admin() && self.source != TransactionSource::Admin {
+            return Err(anyhow!(
+                "Invalid transaction: {}, {:?} is restricted to the admin source",
+                transaction.id,
+                transaction.of_type
+            ));
+        }
+        // disputes, resolves and chargebacks may reference an interest deposit
+        if is_interest_transaction_id(transaction.id)
+            && !matches!(
+                transaction.of_type,
+                TransactionType::Dispute | TransactionType::Resolve | TransactionType::Chargeback
+            )
+        {
+            return Err(anyhow!(
+                "Invalid transaction: {}, tx ids from {} are reserved for interest",
+                transaction.id,
+                INTEREST_TRANSACTION_ID_BASE
+            ));
+        }
+        if transaction.of_type == TransactionType::Adjustment {
+            if transaction.amount == 0.0
+                || transaction.amount.abs() > MAX_INCLUSIVE_TRANSACTION_AMOUNT
             {
                 return Err(anyhow!(
                     "Invalid transaction: {}, amount out of bounds",
                     transaction.id
                 ));
             }
+            if transaction.reason_code.is_none() {
+                return Err(anyhow!(
+                    "Invalid transaction: {}, adjustment without a reason code",
+                    transaction.id
+                ));
+            }
+        }
+        if let Some(house_client_id) = self.house_client_id {
+            if transaction.client_id == house_client_id
+                || transaction.destination_client_id == Some(house_client_id)
+            {
+                return Err(anyhow!(
+                    "Invalid transaction: {}, client {} is reserved for the house account",
+                    transaction.id,
+                    house_client_id
+                ));
+            }
+        }
+        match (transaction.of_type, transaction.destination_client_id) {
+            (TransactionType::Transfer, None) => {
+                return Err(anyhow!(
+                    "Invalid transaction: {}, transfer without a destination client",
+                    transaction.id
+                ))
+            }
+            (TransactionType::Transfer, Some(destination))
+                if destination == transaction.client_id =>
+            {
+                return Err(anyhow!(
+                    "Invalid transaction: {}, transfer to the sending client",
+                    transaction.id
+                ))
+            }
+            (TransactionType::Transfer, _) | (_, None) => {}
+            (_, Some(_)) => {
+                return Err(anyhow!(
+                    "Invalid transaction: {}, only transfers have a destination client",
+                    transaction.id
+                ))
+            }
         }
         Ok(transaction)
     }
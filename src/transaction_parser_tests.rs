@@ -1,8 +1,14 @@
 #![cfg(test)]
 mod tests {
+    use crate::currency::Currency;
+    use crate::test_helpers::{
+        adjustment, chargeback, deposit, dispute, lock, resolve, transfer, unlock, withdrawal,
+    };
     use crate::transaction::{Transaction, TransactionType};
-    use crate::transaction_parser::TransactionParser;
-    use anyhow;
+    use crate::transaction_parser::{
+        CsvDialect, TransactionParser, TransactionSource, LATEST_SCHEMA_VERSION,
+    };
+    use crate::type_aliases::TypeAliases;
     use tokio::sync::mpsc;
     async fn collect_transactions(
         mut receiver: mpsc::Receiver<anyhow::Result<Transaction>>,
@@ -31,66 +37,19 @@ mod tests {
                 sender,
             )
             .parse_transactions()
-            .await;
+            .await
+            .unwrap();
         });
         assert_eq!(
             vec![
-                Transaction {
-                    id: 1,
-                    of_type: TransactionType::Deposit,
-                    client_id: 1,
-                    amount: 10.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 2,
-                    of_type: TransactionType::Deposit,
-                    client_id: 1,
-                    amount: 5.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 2,
-                    of_type: TransactionType::Dispute,
-                    client_id: 1,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 2,
-                    of_type: TransactionType::Resolve,
-                    client_id: 1,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 3,
-                    of_type: TransactionType::Deposit,
-                    client_id: 2,
-                    amount: 20.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 4,
-                    of_type: TransactionType::Withdrawal,
-                    client_id: 2,
-                    amount: 10.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 4,
-                    of_type: TransactionType::Dispute,
-                    client_id: 2,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 4,
-                    of_type: TransactionType::Chargeback,
-                    client_id: 2,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
+                deposit(1, 1, 10.0),
+                deposit(2, 1, 5.0),
+                dispute(2, 1),
+                resolve(2, 1),
+                deposit(3, 2, 20.0),
+                withdrawal(4, 2, 10.0),
+                dispute(4, 2),
+                chargeback(4, 2),
             ],
             collect_transactions(receiver).await
         );
@@ -104,66 +63,19 @@ mod tests {
                 sender,
             )
             .parse_transactions()
-            .await;
+            .await
+            .unwrap();
         });
         assert_eq!(
             vec![
-                Transaction {
-                    id: 1,
-                    of_type: TransactionType::Deposit,
-                    client_id: 1,
-                    amount: 10.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 2,
-                    of_type: TransactionType::Deposit,
-                    client_id: 1,
-                    amount: 5.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 2,
-                    of_type: TransactionType::Dispute,
-                    client_id: 1,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 2,
-                    of_type: TransactionType::Resolve,
-                    client_id: 1,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 3,
-                    of_type: TransactionType::Deposit,
-                    client_id: 2,
-                    amount: 20.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 4,
-                    of_type: TransactionType::Withdrawal,
-                    client_id: 2,
-                    amount: 10.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 4,
-                    of_type: TransactionType::Dispute,
-                    client_id: 2,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
-                Transaction {
-                    id: 4,
-                    of_type: TransactionType::Chargeback,
-                    client_id: 2,
-                    amount: 0.0,
-                    under_dispute: false,
-                },
+                deposit(1, 1, 10.0),
+                deposit(2, 1, 5.0),
+                dispute(2, 1),
+                resolve(2, 1),
+                deposit(3, 2, 20.0),
+                withdrawal(4, 2, 10.0),
+                dispute(4, 2),
+                chargeback(4, 2),
             ],
             collect_transactions(receiver).await
         );
@@ -174,8 +86,492 @@ mod tests {
         tokio::spawn(async move {
             TransactionParser::new("test-transactions-corrupted.csv".to_string(), sender)
                 .parse_transactions()
-                .await;
+                .await
+                .unwrap();
         });
         assert_eq!(count_corrupted_transactions(receiver).await, 4);
     }
+    #[tokio::test]
+    async fn test_admin_transactions_from_partner_source() {
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-admin.csv".to_string(), sender)
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        assert_eq!(count_corrupted_transactions(receiver).await, 4);
+    }
+    #[tokio::test]
+    async fn test_admin_transactions_from_admin_source() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new_with_source(
+                "test-transactions-admin.csv".to_string(),
+                sender,
+                TransactionSource::Admin,
+            )
+            .parse_transactions()
+            .await
+            .unwrap();
+        });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(transaction.ok());
+        }
+        assert_eq!(
+            vec![
+                Some(lock(10, 1)),
+                Some(unlock(11, 1)),
+                Some(adjustment(12, 1, -2.5, 7)),
+                // adjustments without a reason code are rejected
+                None,
+            ],
+            results
+        );
+    }
+    #[tokio::test]
+    async fn test_id_boundaries() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-id-boundaries.csv".to_string(), sender)
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(transaction.map_err(|e| e.to_string()));
+        }
+        assert_eq!(
+            vec![
+                Ok(deposit(1, 70000, 1.0)),
+                // interest deposits can only be referenced
+                Err("Invalid transaction: 18446744073709551615, tx ids from 9223372036854775808 are reserved for interest".to_string()),
+                Ok(dispute(u64::MAX, u32::MAX)),
+                Err(
+                    "Invalid transaction: client id 4294967296 is out of range 0..=4294967295"
+                        .to_string()
+                ),
+                Err(
+                    "Invalid transaction: tx id 18446744073709551616 is out of range 0..=9223372036854775807"
+                        .to_string()
+                ),
+                Err("Invalid transaction: client id -1 is out of range 0..=4294967295".to_string()),
+                Err("Invalid transaction: tx id foo is not a number".to_string()),
+                Err("Invalid transaction: missing client id".to_string()),
+            ],
+            results
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_currency() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-multi-currency.csv".to_string(), sender)
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(transaction.ok());
+        }
+        assert_eq!(
+            vec![
+                Some(Transaction {
+                    currency: Some(Currency::Eur),
+                    ..deposit(1, 1, 10.0)
+                }),
+                Some(Transaction {
+                    currency: Some(Currency::Usd),
+                    ..deposit(2, 1, 5.0)
+                }),
+                Some(dispute(2, 1)),
+                // unsupported currency
+                None,
+            ],
+            results
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_authorization() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-authorization.csv".to_string(), sender)
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(transaction.ok().map(|transaction| transaction.of_type));
+        }
+        assert_eq!(
+            vec![
+                Some(TransactionType::Authorize),
+                Some(TransactionType::Capture),
+                Some(TransactionType::Void),
+                // an authorization needs an amount like a withdrawal
+                None,
+            ],
+            results
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_transfer() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-transfer.csv".to_string(), sender)
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(transaction.ok());
+        }
+        assert_eq!(
+            vec![
+                Some(transfer(1, 1, 2, 5.0)),
+                // no destination
+                None,
+                // transfer to the sending client
+                None,
+                // only transfers have a destination
+                None,
+            ],
+            results
+        );
+    }
+    #[tokio::test]
+    async fn test_house_client_rows() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-transfer.csv".to_string(), sender)
+                .with_house_client_id(2)
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        assert_eq!(
+            "Invalid transaction: 1, client 2 is reserved for the house account",
+            receiver.recv().await.unwrap().unwrap_err().to_string()
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_header_aliases() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-header-aliases.csv".to_string(), sender)
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(transaction.ok().map(|transaction| transaction.id));
+        }
+        // rows with a missing or an extra field are rejected
+        assert_eq!(vec![Some(1), None, None], results);
+    }
+    async fn header_error(path: &str, schema_version: u32) -> String {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let result = TransactionParser::new(path.to_string(), sender)
+            .with_schema_version(schema_version)
+            .parse_transactions()
+            .await;
+        assert!(receiver.recv().await.is_none());
+        result.unwrap_err().to_string()
+    }
+    #[tokio::test]
+    async fn test_parse_invalid_header() {
+        assert_eq!(
+            "Invalid header: unknown column tx_idd in schema version 3",
+            header_error("test-transactions-header-typo.csv", LATEST_SCHEMA_VERSION).await
+        );
+        // destinations came with schema version 2
+        assert_eq!(
+            "Invalid header: unknown column destination in schema version 1",
+            header_error("test-transactions-transfer.csv", 1).await
+        );
+        assert_eq!(
+            "Invalid header: missing column client, amount in schema version 3",
+            header_error(
+                "test-transactions-header-missing.csv",
+                LATEST_SCHEMA_VERSION
+            )
+            .await
+        );
+        assert_eq!(
+            "Invalid header: unsupported schema version 4",
+            header_error("transactions.csv", 4).await
+        );
+    }
+    async fn parse_with_dialect(path: &str, dialect: CsvDialect) -> Vec<Option<Transaction>> {
+        let (sender, mut receiver) = mpsc::channel(1);
+        let parser = TransactionParser::new(path.to_string(), sender).with_dialect(dialect);
+        tokio::spawn(async move { parser.parse_transactions().await.unwrap() });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(transaction.ok());
+        }
+        results
+    }
+    #[tokio::test]
+    async fn test_parse_semicolon_dialect() {
+        let dialect = CsvDialect {
+            delimiter: b';',
+            comment: Some(b'#'),
+            ..CsvDialect::default()
+        };
+        assert_eq!(
+            vec![
+                Some(Transaction {
+                    currency: Some(Currency::Eur),
+                    ..deposit(1, 1, 10.0)
+                }),
+                Some(Transaction {
+                    currency: Some(Currency::Eur),
+                    ..withdrawal(2, 1, 2.5)
+                }),
+            ],
+            parse_with_dialect("test-transactions-semicolon-bom.csv", dialect).await
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_without_header() {
+        let dialect = CsvDialect {
+            has_header: false,
+            ..CsvDialect::default()
+        };
+        assert_eq!(
+            vec![
+                Some(deposit(1, 1, 10.0)),
+                Some(Transaction {
+                    currency: Some(Currency::Usd),
+                    ..deposit(2, 1, 5.0)
+                }),
+                // missing the required columns
+                None,
+                // more fields than columns
+                None,
+            ],
+            parse_with_dialect("test-transactions-no-header.csv", dialect).await
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_compressed() {
+        async fn parse(path: &str) -> Vec<Transaction> {
+            let (sender, receiver) = mpsc::channel(1);
+            let parser = TransactionParser::new(path.to_string(), sender);
+            tokio::spawn(async move { parser.parse_transactions().await.unwrap() });
+            collect_transactions(receiver).await
+        }
+        let expected = parse("test-transactions-expected-column-order.csv").await;
+        assert_eq!(expected, parse("test-transactions-compressed.csv.gz").await);
+        assert_eq!(
+            expected,
+            parse("test-transactions-compressed.csv.zst").await
+        );
+        // detected by its magic bytes
+        assert_eq!(
+            expected,
+            parse("test-transactions-gzip-without-extension.csv").await
+        );
+    }
+    #[tokio::test]
+    async fn test_parse_type_names() {
+        let (sender, mut receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            TransactionParser::new("test-transactions-type-names.csv".to_string(), sender)
+                .with_type_aliases(TypeAliases::load("test-type-aliases.csv").unwrap())
+                .parse_transactions()
+                .await
+                .unwrap();
+        });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(
+                transaction
+                    .map(|transaction| transaction.of_type)
+                    .map_err(|e| e.to_string()),
+            );
+        }
+        assert_eq!(
+            vec![
+                Ok(TransactionType::Deposit),
+                Ok(TransactionType::Deposit),
+                Ok(TransactionType::Withdrawal),
+                Err("Invalid transaction: unknown type payout".to_string()),
+            ],
+            results
+        );
+    }
+    // ids of the parsed rows and errors of the others, with the dead letters written for them
+    async fn parse_with_dead_letter(
+        path: &std::path::Path,
+        configure: fn(TransactionParser) -> TransactionParser,
+    ) -> (Vec<Result<u64, String>>, Vec<u8>) {
+        let dead_letter = path.with_extension("rejected");
+        let (sender, mut receiver) = mpsc::channel(1);
+        let parser = configure(
+            TransactionParser::new(path.to_str().unwrap().to_string(), sender)
+                .with_dead_letter(dead_letter.to_str().unwrap().to_string()),
+        );
+        tokio::spawn(async move { parser.parse_transactions().await.unwrap() });
+        let mut results = vec![];
+        while let Some(transaction) = receiver.recv().await {
+            results.push(
+                transaction
+                    .map(|transaction| transaction.id)
+                    .map_err(|e| e.to_string()),
+            );
+        }
+        (results, std::fs::read(dead_letter).unwrap())
+    }
+    #[tokio::test]
+    async fn test_dead_letter() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("transactions.csv");
+        std::fs::copy("test-transactions-header-aliases.csv", &input).unwrap();
+        let (results, dead_letter) = parse_with_dead_letter(&input, |parser| parser).await;
+        assert_eq!(
+            vec![
+                Ok(1),
+                Err("Invalid transaction: 3 fields, schema version 3 has 4 columns".to_string()),
+                Err("Invalid transaction: 5 fields, schema version 3 has 4 columns".to_string()),
+            ],
+            results
+        );
+        // the rows are kept as they are in the input
+        assert_eq!(
+            "type, client_id, tx_id, amount,line,error\n\
+             deposit, 1, 2,3,\"Invalid transaction: 3 fields, schema version 3 has 4 columns\"\n\
+             deposit, 1, 3, 5.0, 1,4,\"Invalid transaction: 5 fields, schema version 3 has 4 columns\"\n",
+            String::from_utf8(dead_letter.clone()).unwrap()
+        );
+        // the line and error columns are only accepted from a resubmitted dead-letter file
+        let resubmitted = dir.path().join("resubmitted.csv");
+        std::fs::write(&resubmitted, &dead_letter).unwrap();
+        assert_eq!(
+            "Invalid header: unknown column line in schema version 3",
+            header_error(resubmitted.to_str().unwrap(), LATEST_SCHEMA_VERSION).await
+        );
+        // resubmitted as it is, the rows fail the same way and replace their line and error
+        let (results, dead_letter) =
+            parse_with_dead_letter(&resubmitted, |parser| parser.with_resubmission()).await;
+        assert_eq!(
+            vec![
+                Err("Invalid transaction: 3 fields, schema version 3 has 4 columns".to_string()),
+                Err("Invalid transaction: 5 fields, schema version 3 has 4 columns".to_string()),
+            ],
+            results
+        );
+        assert_eq!(
+            "type, client_id, tx_id, amount,line,error\n\
+             deposit, 1, 2,2,\"Invalid transaction: 3 fields, schema version 3 has 4 columns\"\n\
+             deposit, 1, 3, 5.0, 1,3,\"Invalid transaction: 5 fields, schema version 3 has 4 columns\"\n",
+            String::from_utf8(dead_letter).unwrap()
+        );
+        assert_eq!(
+            "Invalid header: a resubmitted dead-letter file ends with the columns line, error",
+            {
+                let (sender, _receiver) = mpsc::channel(1);
+                TransactionParser::new("transactions.csv".to_string(), sender)
+                    .with_resubmission()
+                    .parse_transactions()
+                    .await
+                    .unwrap_err()
+                    .to_string()
+            }
+        );
+    }
+    #[tokio::test]
+    async fn test_dead_letter_keeps_the_input_bytes() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("transactions.csv");
+        std::fs::write(
+            &input,
+            b"\xef\xbb\xbftype;client;tx;amount\r\n\
+              deposit;1;1;1.0\r\n\
+              # the partner's note\r\n\
+              deposit;1;\xff2;1.0\r\n\
+              deposit; 1 ;3;'2;0'\r\n",
+        )
+        .unwrap();
+        let (results, dead_letter) = parse_with_dead_letter(&input, |parser| {
+            parser.with_dialect(CsvDialect {
+                delimiter: b';',
+                quote: b'\'',
+                comment: Some(b'#'),
+                has_header: true,
+            })
+        })
+        .await;
+        assert_eq!(
+            vec![
+                Ok(1),
+                Err(
+                    "Invalid transaction: invalid utf-8: invalid UTF-8 in field 2 near byte index 0"
+                        .to_string()
+                ),
+                Err("Invalid transaction: 3, amount out of bounds".to_string()),
+            ],
+            results
+        );
+        assert_eq!(
+            b"type;client;tx;amount;line;error\r\n\
+              deposit;1;\xff2;1.0;4;Invalid transaction: invalid utf-8: invalid UTF-8 in field 2 near byte index 0\r\n\
+              deposit; 1 ;3;'2;0';5;Invalid transaction: 3, amount out of bounds\r\n"
+                .to_vec(),
+            dead_letter
+        );
+    }
+    #[tokio::test]
+    async fn test_dead_letter_without_header() {
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("transactions.csv");
+        std::fs::copy("test-transactions-no-header.csv", &input).unwrap();
+        let configure = |parser: TransactionParser| {
+            parser.with_dialect(CsvDialect {
+                has_header: false,
+                ..CsvDialect::default()
+            })
+        };
+        let (results, dead_letter) = parse_with_dead_letter(&input, configure).await;
+        let errors: Vec<String> = results.into_iter().filter_map(Result::err).collect();
+        // no header is added, so the file is resubmitted with the dialect of the input
+        assert_eq!(
+            format!(
+                "deposit, 1,3,\"{}\"\n\
+                 deposit, 1, 3, 5.0, , USD, , 2024-01-01, 2024-01-01, extra,4,\"{}\"\n",
+                errors[0], errors[1]
+            ),
+            String::from_utf8(dead_letter).unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn test_dead_letter_on_read_error() {
+        use std::io::Write;
+        let dir = tempfile::tempdir().unwrap();
+        let input = dir.path().join("transactions.csv.gz");
+        let mut encoder = flate2::write::GzEncoder::new(vec![], flate2::Compression::default());
+        encoder.write_all(b"type,client,tx,amount\n").unwrap();
+        for id in 1..=1000 {
+            writeln!(encoder, "deposit,1,{},1.0", id).unwrap();
+        }
+        let compressed = encoder.finish().unwrap();
+        std::fs::write(&input, &compressed[..compressed.len() / 2]).unwrap();
+        let (results, dead_letter) = parse_with_dead_letter(&input, |parser| parser).await;
+        // the input stops being read at the error, which is the last result
+        let error = results.last().unwrap().clone().unwrap_err();
+        assert_eq!(1, results.iter().filter(|result| result.is_err()).count());
+        let dead_letter = String::from_utf8(dead_letter).unwrap();
+        let lines: Vec<&str> = dead_letter.lines().collect();
+        assert_eq!(2, lines.len());
+        assert_eq!("type,client,tx,amount,line,error", lines[0]);
+        assert!(lines[1].starts_with("deposit,1,"));
+        assert!(lines[1].ends_with(&format!(",{}", error)));
+    }
 }
@@ -1,8 +1,24 @@
 use crate::account::Account;
-use crate::transaction::{Transaction, TransactionType};
-use anyhow;
+use crate::client_limits::{ClientLimitsTable, DailyWithdrawal};
+use crate::currency::Currency;
+use crate::error_budget::{Checkpoint, ErrorBudget, ErrorCounts};
+use crate::fee_schedule::{FeeSchedule, DEFAULT_HOUSE_CLIENT_ID};
+use crate::fx_rates::FxRates;
+use crate::history::{HistoryEntry, Outcomes};
+use crate::interest::{interest_transaction_id, is_interest_transaction_id, InterestRates};
+use crate::journal::{Discrepancy, JournalEntry, LedgerAccount, Posting, TrialBalance};
+use crate::ledger_store::{InMemoryLedgerStore, LedgerStore, LedgerUpdate, ScheduledTransaction};
+use crate::lock_policies::{LockPolicies, RiskCounters};
+use crate::metrics::SharedMetrics;
+use crate::rules::{RuleAction, RuleDecision, RulesEngine};
+use crate::run_summary::RunSummary;
+use crate::transaction::{ClientId, FxConversion, Transaction, TransactionId, TransactionType};
+use anyhow::anyhow;
+use chrono::{Local, NaiveDate};
 use log::error;
 use std::collections::HashMap;
+use std::io::{self, Write};
+use std::time::{Duration, Instant};
 use tokio::sync::mpsc;
 
 // probably not reasonable amounts, this is to ensure no overflow
@@ -12,27 +28,295 @@ const MAX_FUNDS: f32 = 1_000_000_000.0;
 #[derive(Debug)]
 pub struct TransactionProcessor {
     receiver: mpsc::Receiver<anyhow::Result<Transaction>>,
-    account_map: HashMap<u16, Account>,
-    transaction_map: HashMap<u32, Transaction>,
+    store: Box<dyn LedgerStore>,
+    fx_rates: FxRates,
+    fee_schedule: FeeSchedule,
+    client_limits: ClientLimitsTable,
+    rules: RulesEngine,
+    lock_policies: LockPolicies,
+    // processing date, moved forward by the booking dates of the rows, the wall clock date
+    // when not set
+    clock: Option<NaiveDate>,
+    // scheduled transaction currently being executed, unscheduled by the same store write
+    unscheduling: Option<ScheduledTransaction>,
+    // admin release currently executing the transaction it releases, recorded by the same write
+    releasing: Option<Transaction>,
+    // reason the transaction currently being executed was rejected for, if any
+    rejection: Option<&'static str>,
+    error_budget: Option<ErrorBudget>,
+    error_counts: ErrorCounts,
+    error_budget_exceeded: bool,
+    // rows taken from the parser, the ones skipped for the checkpoint included
+    rows_taken: u64,
+    checkpoint: Option<Checkpoint>,
+    // the input is not the one the checkpoint was saved for, nothing of it was processed
+    checkpoint_mismatch: Option<anyhow::Error>,
+    outcomes: Outcomes,
+    summary: RunSummary,
+    // wall clock time spent in `execute`
+    elapsed: Duration,
+    metrics: Option<SharedMetrics>,
 }
 
 impl TransactionProcessor {
     pub fn new(receiver: mpsc::Receiver<anyhow::Result<Transaction>>) -> TransactionProcessor {
+        TransactionProcessor::with_store(receiver, Box::new(InMemoryLedgerStore::new()))
+    }
+    pub fn with_store(
+        receiver: mpsc::Receiver<anyhow::Result<Transaction>>,
+        store: Box<dyn LedgerStore>,
+    ) -> TransactionProcessor {
         TransactionProcessor {
             receiver,
-            account_map: HashMap::new(),
-            transaction_map: HashMap::new(),
+            store,
+            fx_rates: FxRates::new(),
+            fee_schedule: FeeSchedule::new(),
+            client_limits: ClientLimitsTable::new(),
+            rules: RulesEngine::new(),
+            lock_policies: LockPolicies::new(),
+            clock: None,
+            unscheduling: None,
+            releasing: None,
+            rejection: None,
+            error_budget: None,
+            error_counts: ErrorCounts::default(),
+            error_budget_exceeded: false,
+            rows_taken: 0,
+            checkpoint: None,
+            checkpoint_mismatch: None,
+            outcomes: Outcomes::new(),
+            summary: RunSummary::default(),
+            elapsed: Duration::ZERO,
+            metrics: None,
+        }
+    }
+    pub fn with_fx_rates(mut self, fx_rates: FxRates) -> TransactionProcessor {
+        self.fx_rates = fx_rates;
+        self
+    }
+    // Fails when the house client has transactions of its own in the ledger, as its account
+    // would mix them with the fee income
+    pub fn with_fee_schedule(
+        mut self,
+        fee_schedule: FeeSchedule,
+    ) -> anyhow::Result<TransactionProcessor> {
+        if let Some(house_client_id) = fee_schedule.house_client_id {
+            if self
+                .store
+                .client_history(house_client_id)?
+                .iter()
+                .any(|entry| {
+                    entry.accepted
+                        && TransactionProcessor::involves_client(
+                            &entry.transaction,
+                            house_client_id,
+                        )
+                })
+            {
+                return Err(anyhow!(
+                    "client {} has transactions of its own and can not be the fee account",
+                    house_client_id
+                ));
+            }
+        }
+        self.fee_schedule = fee_schedule;
+        Ok(self)
+    }
+    pub fn with_client_limits(mut self, client_limits: ClientLimitsTable) -> TransactionProcessor {
+        self.client_limits = client_limits;
+        self
+    }
+    pub fn with_rules(mut self, rules: RulesEngine) -> TransactionProcessor {
+        self.rules = rules;
+        self
+    }
+    pub fn with_lock_policies(mut self, lock_policies: LockPolicies) -> TransactionProcessor {
+        self.lock_policies = lock_policies;
+        self
+    }
+    // processes the input as of the given date instead of today
+    pub fn with_clock(mut self, date: NaiveDate) -> TransactionProcessor {
+        self.clock = Some(date);
+        self
+    }
+    pub fn with_error_budget(mut self, error_budget: ErrorBudget) -> TransactionProcessor {
+        self.error_budget = Some(error_budget);
+        self
+    }
+    // Skips the rows a given up run of the input applied, and keeps track of the rows of this
+    // run in case it is given up too
+    pub fn with_checkpoint(mut self, input: &str) -> anyhow::Result<TransactionProcessor> {
+        self.checkpoint = Some(
+            self.store
+                .get_checkpoint(input)?
+                .unwrap_or_else(|| Checkpoint::new(input)),
+        );
+        Ok(self)
+    }
+    // Starts the open disputes gauge at the disputes already open in the ledger
+    pub fn with_metrics(mut self, metrics: SharedMetrics) -> anyhow::Result<TransactionProcessor> {
+        let mut clients: Vec<ClientId> = self
+            .store
+            .accounts()?
+            .iter()
+            .map(|account| account.client_id)
+            .collect();
+        clients.sort_unstable();
+        clients.dedup();
+        let mut open_disputes = 0;
+        for client_id in clients {
+            for counters in self.store.client_risk_counters(client_id)? {
+                open_disputes += counters.open_disputes as i64;
+            }
+        }
+        metrics.lock().unwrap().add_open_disputes(open_disputes);
+        self.metrics = Some(metrics);
+        Ok(self)
+    }
+    pub fn get_error_counts(&self) -> ErrorCounts {
+        self.error_counts
+    }
+    pub fn get_checkpoint_mismatch(&self) -> Option<&anyhow::Error> {
+        self.checkpoint_mismatch.as_ref()
+    }
+    pub fn get_outcomes(&self) -> &Outcomes {
+        &self.outcomes
+    }
+    pub fn get_run_summary(&self) -> RunSummary {
+        let seconds = self.elapsed.as_secs_f64();
+        RunSummary {
+            rows_read: self.error_counts.rows,
+            rows_parsed: self.error_counts.rows - self.error_counts.corrupted,
+            rejected_at_parse: self.error_counts.corrupted,
+            elapsed_seconds: seconds,
+            rows_per_second: if seconds > 0.0 {
+                self.error_counts.rows as f64 / seconds
+            } else {
+                0.0
+            },
+            ..self.summary.clone()
+        }
+    }
+    // the run stopped early, or its input failed too often, the balances are not to be trusted
+    pub fn error_budget_exceeded(&self) -> bool {
+        self.error_budget_exceeded
+    }
+    pub fn get_account_map(&self) -> HashMap<(ClientId, Currency), Account> {
+        self.store
+            .accounts()
+            .unwrap()
+            .into_iter()
+            .map(|account| ((account.client_id, account.currency), account))
+            .collect()
+    }
+    pub fn get_store(&self) -> &dyn LedgerStore {
+        self.store.as_ref()
+    }
+    pub fn display_statement(&self, client_id: ClientId) {
+        println!(
+            "client,currency,tx,type,amount,status,reason,available,held,total,locked,flags,lock_reason"
+        );
+        for entry in self.store.client_history(client_id).unwrap() {
+            println!(
+                "{},{},{},{},{:.4},{},{},{:.4},{:.4},{:.4},{},{},{}",
+                entry.account.client_id,
+                entry.account.currency,
+                entry.transaction.id,
+                entry.transaction.of_type,
+                entry.transaction.amount,
+                entry.status(),
+                entry.reason.unwrap_or_default(),
+                entry.account.available,
+                entry.account.held,
+                entry.account.available + entry.account.held,
+                entry.account.locked,
+                entry.flags.join(";"),
+                entry
+                    .account
+                    .lock_reason
+                    .map(|policy| policy.to_string())
+                    .unwrap_or_default()
+            )
+        }
+    }
+    pub fn get_trial_balance(&self) -> TrialBalance {
+        TrialBalance::from_journal(&self.store.journal().unwrap())
+    }
+    // accounts whose balances are not what their journal postings add up to
+    pub fn get_discrepancies(&self) -> Vec<Discrepancy> {
+        // fees in a ledger read without a schedule were booked on the default fee account
+        self.get_trial_balance().reconcile(
+            &self.store.accounts().unwrap(),
+            self.fee_schedule
+                .house_client_id
+                .unwrap_or(DEFAULT_HOUSE_CLIENT_ID),
+        )
+    }
+    pub fn display_trial_balance(&self) {
+        self.write_trial_balance(&mut io::stdout(), &mut io::stderr())
+            .unwrap();
+    }
+    // The balances go to `out` and anything that does not add up to `warnings`, no logger is
+    // installed so the invariant checks are written out like the report itself
+    pub fn write_trial_balance<O: Write, W: Write>(
+        &self,
+        out: &mut O,
+        warnings: &mut W,
+    ) -> io::Result<()> {
+        let trial_balance = self.get_trial_balance();
+        writeln!(out, "account,currency,balance")?;
+        for ((account, currency), balance) in &trial_balance.balances {
+            writeln!(out, "{},{},{:.4}", account, currency, balance)?;
+        }
+        for (currency, total) in trial_balance.totals() {
+            writeln!(out, "total,{},{:.4}", currency, total)?;
         }
+        if !trial_balance.is_balanced() {
+            writeln!(warnings, "trial balance does not sum to zero")?;
+        }
+        for discrepancy in self.get_discrepancies() {
+            writeln!(warnings, "{}", discrepancy)?;
+        }
+        Ok(())
     }
-    pub fn get_account_map(&self) -> HashMap<u16, Account> {
-        self.account_map.clone()
+    // How the run went and how it changed the balances, for a run against a copy of the ledger
+    pub fn display_validation(&self, before: &HashMap<(ClientId, Currency), Account>) {
+        println!("status,reason,count");
+        for (status, reason, count) in self.outcomes.counts() {
+            println!("{},{},{}", status, reason, count);
+        }
+        println!("corrupted,,{}", self.error_counts.corrupted);
+        println!();
+        println!("client,currency,available,held,total");
+        let mut accounts = self.store.accounts().unwrap();
+        accounts.sort_by_key(|account| (account.client_id, account.currency));
+        for account in accounts {
+            let (available, held) = match before.get(&(account.client_id, account.currency)) {
+                Some(before) => (
+                    account.available - before.available,
+                    account.held - before.held,
+                ),
+                None => (account.available, account.held),
+            };
+            if available != 0.0 || held != 0.0 {
+                println!(
+                    "{},{},{:.4},{:.4},{:.4}",
+                    account.client_id,
+                    account.currency,
+                    available,
+                    held,
+                    available + held
+                );
+            }
+        }
     }
     pub fn display_accounts(&self) {
-        println!("client,available,held,total,locked");
-        for (_, account) in &self.account_map {
+        println!("client,currency,available,held,total,locked");
+        for account in self.store.accounts().unwrap() {
             println!(
-                "{},{:.4},{:.4},{:.4},{}",
+                "{},{},{:.4},{:.4},{:.4},{}",
                 account.client_id,
+                account.currency,
                 account.available,
                 account.held,
                 account.available + account.held,
@@ -41,192 +325,1463 @@ impl TransactionProcessor {
         }
     }
     pub async fn execute(&mut self) -> &TransactionProcessor {
+        let started = Instant::now();
+        self.execute_due_transactions();
+        match self.take_checkpointed_rows().await {
+            Ok(rows) => self.process_rows(rows).await,
+            Err(e) => {
+                // nothing of the input is applied, and the checkpoint is kept for the input
+                // it belongs to
+                self.checkpoint = None;
+                self.checkpoint_mismatch = Some(e);
+                self.receiver.close();
+            }
+        }
+        self.check_error_budget(true);
+        // a rerun of the input continues where a given up run stopped, after a run that was
+        // not given up there is nothing to continue
+        if let Some(checkpoint) = &self.checkpoint {
+            let result = if self.error_budget_exceeded {
+                self.store.put_checkpoint(checkpoint.clone())
+            } else {
+                self.store.remove_checkpoint(&checkpoint.input)
+            };
+            if let Err(e) = result {
+                eprintln!(
+                    "failed to save the checkpoint of {}, {}",
+                    checkpoint.input, e
+                );
+            }
+        }
+        if let Err(e) = self.store.flush() {
+            error!("failed to flush ledger store, {:?}", e);
+        }
+        self.elapsed += started.elapsed();
+        self
+    }
+    // Takes the rows a given up run of the input consumed and checks that the ones it applied
+    // are still the same before they are skipped, the others are returned to be processed again
+    async fn take_checkpointed_rows(
+        &mut self,
+    ) -> anyhow::Result<Vec<(u64, anyhow::Result<Transaction>)>> {
+        let checkpoint = match &self.checkpoint {
+            Some(checkpoint) => checkpoint.clone(),
+            None => return Ok(vec![]),
+        };
+        let mut rows = vec![];
+        let mut fingerprint = 0;
+        while self.rows_taken < checkpoint.consumed {
+            let result_transaction = match self.receiver.recv().await {
+                Some(result_transaction) => result_transaction,
+                None => break,
+            };
+            let row = self.take_row();
+            if !checkpoint.applied(row) {
+                rows.push((row, result_transaction));
+                continue;
+            }
+            match result_transaction {
+                Ok(transaction) => {
+                    fingerprint = Checkpoint::add_row(fingerprint, row, &transaction);
+                    self.summary.rows_skipped += 1;
+                }
+                Err(_) => break,
+            }
+        }
+        if self.rows_taken < checkpoint.consumed || fingerprint != checkpoint.fingerprint {
+            return Err(anyhow!(
+                "{} does not have the rows the run that was given up applied, rows of a rerun \
+                 must be fixed in place",
+                checkpoint.input
+            ));
+        }
+        Ok(rows)
+    }
+    async fn process_rows(&mut self, rows: Vec<(u64, anyhow::Result<Transaction>)>) {
+        for (row, result_transaction) in rows {
+            if self.process_row(row, result_transaction) {
+                return;
+            }
+        }
         while let Some(result_transaction) = self.receiver.recv().await {
-            let transaction = match result_transaction {
-                Ok(transaction) => transaction,
+            let row = self.take_row();
+            if self.process_row(row, result_transaction) {
+                return;
+            }
+        }
+    }
+    fn take_row(&mut self) -> u64 {
+        self.rows_taken += 1;
+        if let Some(metrics) = &self.metrics {
+            metrics.lock().unwrap().record_row_received();
+        }
+        self.rows_taken
+    }
+    // Processes a row of the input, true once the error budget stops the run
+    fn process_row(&mut self, row: u64, result_transaction: anyhow::Result<Transaction>) -> bool {
+        self.error_counts.rows += 1;
+        let transaction = match result_transaction {
+            Ok(transaction) => transaction,
+            Err(e) => {
+                error!("corrupted transaction, {:?}", e);
+                self.error_counts.corrupted += 1;
+                if let Some(metrics) = &self.metrics {
+                    metrics.lock().unwrap().record_corrupted_row();
+                }
+                self.record_row(row, None);
+                return self.check_error_budget(false);
+            }
+        };
+        if let Some(date) = transaction.booked_on {
+            self.clock = Some(self.business_date().max(date));
+        }
+        // the row's booking date or the wall clock may have passed a scheduled date
+        self.execute_due_transactions();
+        let rejected = self.error_counts.rejected;
+        let processing_started = Instant::now();
+        let result = match transaction.effective_at {
+            Some(date) if date > self.business_date() => self.schedule_transaction(transaction),
+            _ => self.execute_transaction(transaction),
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics
+                .lock()
+                .unwrap()
+                .observe_latency(processing_started.elapsed());
+        }
+        let applied = result.is_ok() && self.error_counts.rejected == rejected;
+        self.record_row(row, Some(&transaction).filter(|_| applied));
+        if let Err(e) = result {
+            error!(
+                "ledger store failure on transaction {:?}, {:?}",
+                transaction, e
+            );
+        }
+        self.check_error_budget(false)
+    }
+    // the transaction is only given for a row that was applied
+    fn record_row(&mut self, row: u64, applied: Option<&Transaction>) {
+        if let Some(checkpoint) = &mut self.checkpoint {
+            checkpoint.record(row, applied);
+        }
+    }
+    // Closes the input once the budget is exceeded, so the parser stops reading it
+    fn check_error_budget(&mut self, input_done: bool) -> bool {
+        if self.error_budget_exceeded {
+            return true;
+        }
+        self.error_budget_exceeded = self
+            .error_budget
+            .is_some_and(|budget| budget.exceeded(&self.error_counts, input_done));
+        if self.error_budget_exceeded {
+            self.receiver.close();
+        }
+        self.error_budget_exceeded
+    }
+    // Books a day of interest on the available funds of every account as a deposit through
+    // the normal deposit path, so it shows up in the history and can be disputed.
+    pub fn accrue_interest(&mut self, interest_rates: &InterestRates) {
+        let today = self.business_date();
+        let mut accounts = match self.store.accounts() {
+            Ok(accounts) => accounts,
+            Err(e) => {
+                error!("failed to read accounts, {:?}", e);
+                return;
+            }
+        };
+        accounts.sort_by_key(|account| (account.client_id, account.currency));
+        for account in accounts {
+            // the house account holds the fee income, not client funds
+            if Some(account.client_id) == self.fee_schedule.house_client_id {
+                continue;
+            }
+            // a locked client earns nothing, booking it would only be rejected as a failed row
+            if account.locked {
+                continue;
+            }
+            let amount = interest_rates.daily_interest(
+                account.client_id,
+                account.currency,
+                account.available,
+                today,
+            );
+            if amount <= 0.0 {
+                continue;
+            }
+            let transaction = Transaction {
+                of_type: TransactionType::Deposit,
+                client_id: account.client_id,
+                id: interest_transaction_id(account.client_id, account.currency, today),
+                amount,
+                under_dispute: false,
+                reason_code: None,
+                currency: Some(account.currency),
+                fx_conversion: None,
+                fee: 0.0,
+                destination_client_id: None,
+                effective_at: None,
+                booked_on: None,
+                authorized_on: None,
+            };
+            let result = match self.store.get_transaction(transaction.id) {
+                // system interest, the partner rules do not apply to it
+                Ok(None) => self.execute_decided_transaction(transaction, vec![]),
+                // the id is made of the client, currency and day, the day was accrued already
+                Ok(Some(stored_transaction))
+                    if stored_transaction.client_id == transaction.client_id =>
+                {
+                    Ok(())
+                }
+                Ok(Some(_)) => Err(anyhow!(
+                    "interest transaction id {} is taken",
+                    transaction.id
+                )),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                error!(
+                    "ledger store failure on transaction {:?}, {:?}",
+                    transaction, e
+                );
+            }
+        }
+        if let Err(e) = self.store.flush() {
+            error!("failed to flush ledger store, {:?}", e);
+        }
+    }
+    // Keeps a future-dated transaction until the processing date reaches it, the client's
+    // history shows it as scheduled until then
+    fn schedule_transaction(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+        let account = self
+            .store
+            .get_account(transaction.client_id, transaction.account_currency())?
+            .unwrap_or_else(|| {
+                Account::new_with_client(transaction.client_id, transaction.account_currency())
+            });
+        self.store.apply(LedgerUpdate {
+            history: vec![HistoryEntry::scheduled(transaction, account)],
+            scheduled: vec![transaction],
+            ..LedgerUpdate::default()
+        })?;
+        *self
+            .summary
+            .scheduled
+            .entry(transaction.of_type.to_string())
+            .or_default() += 1;
+        self.record_outcome(&transaction, "scheduled", None);
+        Ok(())
+    }
+    // applies the scheduled transactions whose effective date has come
+    fn execute_due_transactions(&mut self) {
+        loop {
+            let scheduled = match self.store.next_due(self.business_date()) {
+                Ok(Some(scheduled)) => scheduled,
+                Ok(None) => return,
                 Err(e) => {
-                    error!("corrupted transaction, {:?}", e);
-                    continue;
+                    error!("failed to read scheduled transactions, {:?}", e);
+                    return;
                 }
             };
-            if self.account_is_locked(transaction.client_id) {
-                self.transaction_can_not_be_performed_error(&transaction, "account is locked");
-                continue;
+            self.unscheduling = Some(scheduled);
+            let transaction = scheduled.transaction;
+            let result = self.execute_transaction(transaction);
+            // a transaction the failed write left scheduled would come up again right away,
+            // it is retried on the next row instead
+            if self.unscheduling.take().is_some() {
+                error!(
+                    "ledger store failure on scheduled transaction {:?}, {:?}",
+                    transaction, result
+                );
+                return;
+            }
+            if let Err(e) = result {
+                error!(
+                    "ledger store failure on transaction {:?}, {:?}",
+                    transaction, e
+                );
             }
+        }
+    }
+    fn execute_transaction(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+        let decisions = if transaction.of_type.is_admin() {
+            vec![]
+        } else {
+            self.rules.evaluate(&transaction)
+        };
+        self.execute_decided_transaction(transaction, decisions)
+    }
+    // executes the transaction unless one of the rule decisions on it stops it
+    fn execute_decided_transaction(
+        &mut self,
+        transaction: Transaction,
+        decisions: Vec<RuleDecision>,
+    ) -> anyhow::Result<()> {
+        self.rejection = None;
+        let flags: Vec<String> = decisions
+            .iter()
+            .filter(|decision| decision.action == RuleAction::Flag)
+            .map(|decision| decision.rule.clone())
+            .collect();
+        let stopped_by = decisions
+            .iter()
+            .filter(|decision| decision.action != RuleAction::Flag)
+            .max_by_key(|decision| decision.action);
+        let house_client_id = self.fee_schedule.house_client_id;
+        let update = if house_client_id
+            .is_some_and(|client_id| TransactionProcessor::involves_client(&transaction, client_id))
+        {
+            self.transaction_can_not_be_performed_error(
+                &transaction,
+                "client is the house account",
+            );
+            None
+        } else if transaction.of_type.has_own_id()
+            && self.store.get_transaction(transaction.id)?.is_some()
+        {
+            self.transaction_can_not_be_performed_error(
+                &transaction,
+                "transaction id already exists",
+            );
+            None
+        } else if !transaction.of_type.is_admin()
+            && self.account_is_locked(transaction.client_id)?
+        {
+            self.transaction_can_not_be_performed_error(&transaction, "account is locked");
+            None
+        } else if let Some(decision) = stopped_by {
+            error!(
+                "Transaction: {:?} stopped by rule: {}",
+                transaction, decision.rule
+            );
+            None
+        } else {
             match transaction.of_type {
-                TransactionType::Deposit => self.execute_deposit(transaction),
-                TransactionType::Withdrawal => self.execute_withdrawal(transaction),
-                TransactionType::Dispute => self.execute_dispute(transaction),
-                TransactionType::Resolve => self.execute_resolve(transaction),
-                TransactionType::Chargeback => self.execute_chargeback(transaction),
+                TransactionType::Deposit => self.execute_deposit(transaction)?,
+                TransactionType::Withdrawal => self.execute_withdrawal(transaction)?,
+                TransactionType::Dispute => self.execute_dispute(transaction)?,
+                TransactionType::Resolve => self.execute_resolve(transaction)?,
+                TransactionType::Chargeback => self.execute_chargeback(transaction)?,
+                TransactionType::Lock => self.execute_set_lock(transaction, true)?,
+                TransactionType::Unlock => self.execute_set_lock(transaction, false)?,
+                TransactionType::Adjustment => self.execute_adjustment(transaction)?,
+                TransactionType::Authorize => self.execute_authorize(transaction)?,
+                TransactionType::Capture => self.execute_capture(transaction)?,
+                TransactionType::Void => self.execute_void(transaction)?,
+                TransactionType::Transfer => self.execute_transfer(transaction)?,
+                TransactionType::Release => match self.held_transaction(&transaction)? {
+                    Some(held_transaction) => {
+                        self.releasing = Some(transaction);
+                        let result = self.execute_decided_transaction(held_transaction, vec![]);
+                        self.releasing = None;
+                        return result;
+                    }
+                    None => None,
+                },
+            }
+        };
+        match update {
+            Some(mut update) => {
+                if let Some(entry) = update.journal.iter().find(|entry| !entry.is_balanced()) {
+                    return Err(anyhow!("unbalanced journal entry {:?}", entry));
+                }
+                // admin transactions are the way out of a policy lock, so they do not trigger one.
+                // Every client the update touches is checked, a transfer and its disputes
+                // change the receiving client too.
+                if !transaction.of_type.is_admin() {
+                    let mut client_ids: Vec<ClientId> = update
+                        .accounts
+                        .iter()
+                        .map(|account| account.client_id)
+                        .chain(std::iter::once(transaction.client_id))
+                        .filter(|client_id| Some(*client_id) != self.fee_schedule.house_client_id)
+                        .collect();
+                    client_ids.sort_unstable();
+                    client_ids.dedup();
+                    for client_id in client_ids {
+                        self.apply_lock_policies(client_id, &mut update)?;
+                    }
+                }
+                let mut accounts = update.accounts.clone();
+                // a disputed transfer changes only the receiving client, the disputing
+                // one still gets the entry in its own history
+                if !accounts
+                    .iter()
+                    .any(|account| account.client_id == transaction.client_id)
+                {
+                    accounts.extend(
+                        self.store
+                            .get_account(transaction.client_id, transaction.account_currency())?,
+                    );
+                }
+                for account in accounts {
+                    update.history.push(HistoryEntry {
+                        flags: flags.clone(),
+                        ..HistoryEntry::accepted(transaction, account)
+                    });
+                }
+                if let Some(release) = self.releasing.take() {
+                    let account = update
+                        .history
+                        .iter()
+                        .rev()
+                        .find(|entry| entry.account.client_id == release.client_id)
+                        .map(|entry| entry.account);
+                    update
+                        .history
+                        .extend(account.map(|account| HistoryEntry::accepted(release, account)));
+                }
+                update.unscheduled.extend(self.unscheduling);
+                let summary = self.accepted_summary(&transaction, &update)?;
+                self.store.apply(update)?;
+                self.unscheduling = None;
+                // nothing is counted for an update the store failed to apply
+                self.summary.add(&summary);
+                if let Some(metrics) = &self.metrics {
+                    metrics.lock().unwrap().add_open_disputes(
+                        summary.disputes_opened as i64
+                            - summary.disputes_resolved as i64
+                            - summary.disputes_charged_back as i64,
+                    );
+                }
+                self.record_outcome(&transaction, "accepted", None);
+                Ok(())
+            }
+            None => {
+                let account = self
+                    .store
+                    .get_account(transaction.client_id, transaction.account_currency())?
+                    .unwrap_or_else(|| {
+                        Account::new_with_client(
+                            transaction.client_id,
+                            transaction.account_currency(),
+                        )
+                    });
+                let entry = match (self.rejection.take(), stopped_by) {
+                    (Some(reason), _) => HistoryEntry::rejected(transaction, account, reason),
+                    (None, Some(decision)) if decision.action == RuleAction::Hold => {
+                        HistoryEntry::held(transaction, account, &decision.rule)
+                    }
+                    (None, Some(decision)) => {
+                        HistoryEntry::rejected(transaction, account, &decision.rule)
+                    }
+                    (None, None) => HistoryEntry::rejected(transaction, account, "unknown"),
+                };
+                let (held, status, reason) = (entry.held, entry.status(), entry.reason.clone());
+                // the release of a transaction that is rejected now can be retried later
+                let release = self.releasing.take().map(|release| HistoryEntry {
+                    transaction: release,
+                    ..entry.clone()
+                });
+                self.store.apply(LedgerUpdate {
+                    history: std::iter::once(HistoryEntry { flags, ..entry })
+                        .chain(release)
+                        .collect(),
+                    unscheduled: self.unscheduling.into_iter().collect(),
+                    ..LedgerUpdate::default()
+                })?;
+                let counts = if held {
+                    &mut self.summary.held
+                } else {
+                    self.error_counts.rejected += 1;
+                    &mut self.summary.rejected
+                };
+                *counts.entry(transaction.of_type.to_string()).or_default() += 1;
+                self.record_outcome(&transaction, status, reason.as_deref());
+                self.unscheduling = None;
+                Ok(())
+            }
+        }
+    }
+    fn record_outcome(&mut self, transaction: &Transaction, status: &str, reason: Option<&str>) {
+        self.outcomes.record(status, reason);
+        if let Some(metrics) = &self.metrics {
+            metrics.lock().unwrap().record_transaction(
+                &transaction.of_type.to_string(),
+                status,
+                reason,
+            );
+        }
+    }
+    // what an accepted transaction adds to the run summary, read before its update is applied
+    fn accepted_summary(
+        &self,
+        transaction: &Transaction,
+        update: &LedgerUpdate,
+    ) -> anyhow::Result<RunSummary> {
+        let mut summary = RunSummary::default();
+        *summary
+            .accepted
+            .entry(transaction.of_type.to_string())
+            .or_default() += 1;
+        match transaction.of_type {
+            TransactionType::Dispute => summary.disputes_opened += 1,
+            TransactionType::Resolve => summary.disputes_resolved += 1,
+            TransactionType::Chargeback => summary.disputes_charged_back += 1,
+            _ => {}
+        }
+        for account in &update.accounts {
+            match self
+                .store
+                .get_account(account.client_id, account.currency)?
+            {
+                None => summary.accounts_created += 1,
+                Some(before) if account.locked && !before.locked => summary.accounts_locked += 1,
+                Some(_) => {}
+            }
+        }
+        // money enters the ledger as a credit of the settlement account and leaves as a debit
+        for posting in update.journal.iter().flat_map(|entry| &entry.postings) {
+            if posting.account != LedgerAccount::ExternalSettlement {
+                continue;
+            }
+            let totals = if posting.amount < 0.0 {
+                &mut summary.deposited
+            } else {
+                &mut summary.withdrawn
             };
+            *totals.entry(posting.currency).or_default() += posting.amount.abs();
         }
-        self
+        Ok(summary)
     }
-    fn execute_deposit(&mut self, transaction: Transaction) {
-        if !self.insert_or_update_account(&transaction, transaction.amount, 0.0, false) {
-            return;
+    fn execute_deposit(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        // interest is paid by the house, no partner fee is taken out of it
+        let fee = if is_interest_transaction_id(transaction.id) {
+            0.0
+        } else {
+            self.fee_schedule
+                .fee(TransactionType::Deposit, transaction.account_amount())
+        };
+        if fee > transaction.account_amount() {
+            self.transaction_can_not_be_performed_error(
+                &transaction,
+                "fee exceeds the deposit amount",
+            );
+            return Ok(None);
         }
-        self.insert_transaction(transaction);
+        let transaction = Transaction { fee, ..transaction };
+        let account = match self.updated_account(
+            &transaction,
+            transaction.account_amount() - fee,
+            0.0,
+            false,
+        )? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let mut accounts = vec![account];
+        match self.updated_house_account(&transaction, fee)? {
+            Some(house_account) => accounts.extend(house_account),
+            None => return Ok(None),
+        }
+        let currency = transaction.account_currency();
+        let amount = transaction.account_amount() as f64;
+        // interest does not come from outside, it is a cost of the house
+        let source = if is_interest_transaction_id(transaction.id) {
+            LedgerAccount::InterestExpense
+        } else {
+            LedgerAccount::ExternalSettlement
+        };
+        let journal_entry = JournalEntry::new(
+            transaction,
+            vec![
+                Posting::new(
+                    LedgerAccount::ClientAvailable(transaction.client_id),
+                    currency,
+                    amount - fee as f64,
+                ),
+                Posting::new(LedgerAccount::Fees, currency, fee as f64),
+                Posting::new(source, currency, -amount),
+            ],
+        );
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            ..LedgerUpdate::new(accounts, vec![transaction])
+        }))
     }
-    fn execute_withdrawal(&mut self, transaction: Transaction) {
-        if !self.account_has_sufficient_funds(&transaction) {
-            return;
+    fn execute_withdrawal(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        let (transaction, daily_withdrawal) = match self.checked_withdrawal(transaction)? {
+            Some(checked) => checked,
+            None => return Ok(None),
+        };
+        let account = match self.updated_account(
+            &transaction,
+            -(transaction.account_amount() + transaction.fee),
+            0.0,
+            false,
+        )? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let mut accounts = vec![account];
+        match self.updated_house_account(&transaction, transaction.fee)? {
+            Some(house_account) => accounts.extend(house_account),
+            None => return Ok(None),
         }
-        if !self.insert_or_update_account(&transaction, -transaction.amount, 0.0, false) {
-            return;
+        let currency = transaction.account_currency();
+        let amount = transaction.account_amount() as f64;
+        let fee = transaction.fee as f64;
+        let journal_entry = JournalEntry::new(
+            transaction,
+            vec![
+                Posting::new(
+                    LedgerAccount::ClientAvailable(transaction.client_id),
+                    currency,
+                    -(amount + fee),
+                ),
+                Posting::new(LedgerAccount::Fees, currency, fee),
+                Posting::new(LedgerAccount::ExternalSettlement, currency, amount),
+            ],
+        );
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            daily_withdrawals: vec![daily_withdrawal],
+            ..LedgerUpdate::new(accounts, vec![transaction])
+        }))
+    }
+    fn execute_authorize(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        let (transaction, daily_withdrawal) = match self.checked_withdrawal(transaction)? {
+            Some(checked) => checked,
+            None => return Ok(None),
+        };
+        // kept so a void can give the amount back to the day it was counted against
+        let transaction = Transaction {
+            authorized_on: Some(daily_withdrawal.date),
+            ..transaction
+        };
+        let held_amount = transaction.account_amount() + transaction.fee;
+        let account = match self.updated_account(&transaction, -held_amount, held_amount, false)? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let journal_entry = JournalEntry::new(
+            transaction,
+            self.held_funds_postings(&transaction, held_amount),
+        );
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            daily_withdrawals: vec![daily_withdrawal],
+            ..LedgerUpdate::new(vec![account], vec![transaction])
+        }))
+    }
+    fn execute_capture(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        let authorization = match self.pending_authorization(&transaction)? {
+            Some(authorization) => authorization,
+            None => return Ok(None),
+        };
+        let held_amount = authorization.account_amount() + authorization.fee;
+        let account = match self.updated_account(&authorization, 0.0, -held_amount, false)? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let mut accounts = vec![account];
+        match self.updated_house_account(&authorization, authorization.fee)? {
+            Some(house_account) => accounts.extend(house_account),
+            None => return Ok(None),
         }
-        self.insert_transaction(transaction);
+        let currency = authorization.account_currency();
+        let amount = authorization.account_amount() as f64;
+        let fee = authorization.fee as f64;
+        let journal_entry = JournalEntry::new(
+            transaction,
+            vec![
+                Posting::new(
+                    LedgerAccount::ClientHeld(transaction.client_id),
+                    currency,
+                    -(amount + fee),
+                ),
+                Posting::new(LedgerAccount::Fees, currency, fee),
+                Posting::new(LedgerAccount::ExternalSettlement, currency, amount),
+            ],
+        );
+        // from here on the authorization is an ordinary withdrawal, disputes included
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            ..LedgerUpdate::new(
+                accounts,
+                vec![Transaction {
+                    of_type: TransactionType::Withdrawal,
+                    ..authorization
+                }],
+            )
+        }))
     }
-    fn execute_dispute(&mut self, transaction: Transaction) {
-        if !self.transaction_exists_and_not_under_dispute(&transaction) {
-            return;
+    fn execute_void(&mut self, transaction: Transaction) -> anyhow::Result<Option<LedgerUpdate>> {
+        let authorization = match self.pending_authorization(&transaction)? {
+            Some(authorization) => authorization,
+            None => return Ok(None),
+        };
+        let held_amount = authorization.account_amount() + authorization.fee;
+        let account =
+            match self.updated_account(&authorization, held_amount, -held_amount, false)? {
+                Some(account) => account,
+                None => return Ok(None),
+            };
+        let journal_entry = JournalEntry::new(
+            transaction,
+            self.held_funds_postings(&authorization, -held_amount),
+        );
+        // a capture keeps the authorized amount in the day's withdrawals, a void takes it out
+        let currency = authorization.account_currency();
+        let date = authorization
+            .authorized_on
+            .unwrap_or_else(|| self.business_date());
+        let withdrawn = self
+            .store
+            .get_daily_withdrawal(authorization.client_id, currency, date)?
+            - authorization.account_amount();
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            daily_withdrawals: vec![DailyWithdrawal {
+                client_id: authorization.client_id,
+                currency,
+                date,
+                amount: withdrawn.max(0.0),
+            }],
+            ..LedgerUpdate::new(
+                vec![account],
+                vec![Transaction {
+                    of_type: TransactionType::Void,
+                    ..authorization
+                }],
+            )
+        }))
+    }
+    fn execute_transfer(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        let destination_client_id = match transaction.destination_client_id {
+            Some(destination_client_id) => destination_client_id,
+            None => return Err(anyhow!("transfer {} without a destination", transaction.id)),
+        };
+        if self.account_is_locked(destination_client_id)? {
+            self.transaction_can_not_be_performed_error(
+                &transaction,
+                "destination account is locked",
+            );
+            return Ok(None);
         }
-        let disputed_transaction = self.transaction_map[&transaction.id];
+        // the transfer leaves the client like a withdrawal, so the same limits apply
+        let daily_withdrawal = match self.updated_daily_withdrawal(&transaction)? {
+            Some(daily_withdrawal) => daily_withdrawal,
+            None => return Ok(None),
+        };
+        if !self.account_has_sufficient_funds(&transaction)? {
+            return Ok(None);
+        }
+        let source_account =
+            match self.updated_account(&transaction, -transaction.amount, 0.0, false)? {
+                Some(account) => account,
+                None => return Ok(None),
+            };
+        let destination_account = match self.updated_client_account(
+            &transaction,
+            destination_client_id,
+            transaction.amount,
+            0.0,
+            false,
+        )? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let currency = transaction.account_currency();
+        let journal_entry = JournalEntry::new(
+            transaction,
+            vec![
+                Posting::new(
+                    LedgerAccount::ClientAvailable(transaction.client_id),
+                    currency,
+                    -transaction.amount as f64,
+                ),
+                Posting::new(
+                    LedgerAccount::ClientAvailable(destination_client_id),
+                    currency,
+                    transaction.amount as f64,
+                ),
+            ],
+        );
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            daily_withdrawals: vec![daily_withdrawal],
+            ..LedgerUpdate::new(vec![source_account, destination_account], vec![transaction])
+        }))
+    }
+    fn execute_dispute(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        if !self.transaction_exists_and_not_under_dispute(&transaction)? {
+            return Ok(None);
+        }
+        let disputed_transaction = self.get_stored_transaction(transaction.id)?;
         if !self.transactions_reference_the_same_client(&transaction, &disputed_transaction) {
-            return;
+            return Ok(None);
+        }
+        if !self.transactions_reference_the_same_currency(&transaction, &disputed_transaction) {
+            return Ok(None);
+        }
+        if !self.transaction_is_disputable(&transaction, &disputed_transaction) {
+            return Ok(None);
         }
         let disputed_amount = self.get_disputed_amount_from_transaction(&disputed_transaction);
-        if !self.insert_or_update_account(
+        let account = match self.updated_client_account(
             &disputed_transaction,
+            TransactionProcessor::disputed_client_id(&disputed_transaction),
             -disputed_amount,
             disputed_amount,
             false,
-        ) {
-            return;
-        }
-        self.set_transaction_under_dispute(&disputed_transaction, true);
+        )? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let journal_entry = JournalEntry::new(
+            transaction,
+            self.held_funds_postings(&disputed_transaction, disputed_amount),
+        );
+        let risk_counters = self.updated_risk_counters(&disputed_transaction, 1, 0.0)?;
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            risk_counters: vec![risk_counters],
+            ..LedgerUpdate::new(
+                vec![account],
+                vec![Transaction {
+                    under_dispute: true,
+                    ..disputed_transaction
+                }],
+            )
+        }))
     }
-    fn execute_resolve(&mut self, transaction: Transaction) {
-        if !self.transaction_exists_and_under_dispute(&transaction) {
-            return;
+    fn execute_resolve(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        if !self.transaction_exists_and_under_dispute(&transaction)? {
+            return Ok(None);
         }
-        let disputed_transaction = self.transaction_map[&transaction.id];
+        let disputed_transaction = self.get_stored_transaction(transaction.id)?;
         if !self.transactions_reference_the_same_client(&transaction, &disputed_transaction) {
-            return;
+            return Ok(None);
+        }
+        if !self.transactions_reference_the_same_currency(&transaction, &disputed_transaction) {
+            return Ok(None);
         }
         let disputed_amount = self.get_disputed_amount_from_transaction(&disputed_transaction);
-        if !self.insert_or_update_account(
+        let account = match self.updated_client_account(
             &disputed_transaction,
+            TransactionProcessor::disputed_client_id(&disputed_transaction),
             disputed_amount,
             -disputed_amount,
             false,
-        ) {
-            return;
-        }
-        self.set_transaction_under_dispute(&disputed_transaction, false);
+        )? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let journal_entry = JournalEntry::new(
+            transaction,
+            self.held_funds_postings(&disputed_transaction, -disputed_amount),
+        );
+        let risk_counters = self.updated_risk_counters(&disputed_transaction, -1, 0.0)?;
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            risk_counters: vec![risk_counters],
+            ..LedgerUpdate::new(
+                vec![account],
+                vec![Transaction {
+                    under_dispute: false,
+                    ..disputed_transaction
+                }],
+            )
+        }))
     }
-    fn execute_chargeback(&mut self, transaction: Transaction) {
-        if !self.transaction_exists_and_under_dispute(&transaction) {
-            return;
+    fn execute_chargeback(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        if !self.transaction_exists_and_under_dispute(&transaction)? {
+            return Ok(None);
         }
-        let disputed_transaction = self.transaction_map[&transaction.id];
+        let disputed_transaction = self.get_stored_transaction(transaction.id)?;
         if !self.transactions_reference_the_same_client(&transaction, &disputed_transaction) {
-            return;
+            return Ok(None);
+        }
+        if !self.transactions_reference_the_same_currency(&transaction, &disputed_transaction) {
+            return Ok(None);
         }
         let disputed_amount = self.get_disputed_amount_from_transaction(&disputed_transaction);
-        if !self.insert_or_update_account(&disputed_transaction, 0.0, -disputed_amount, true) {
-            return;
+        let holder_client_id = TransactionProcessor::disputed_client_id(&disputed_transaction);
+        let account = match self.updated_client_account(
+            &disputed_transaction,
+            holder_client_id,
+            0.0,
+            -disputed_amount,
+            true,
+        )? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let mut accounts = vec![account];
+        accounts.extend(self.other_client_accounts_with_lock(&account, true)?);
+        // a charged back transfer is reversed, the funds go back to the sending client
+        let reversal_account = if disputed_transaction.of_type == TransactionType::Transfer {
+            match self.updated_account(&disputed_transaction, disputed_amount, 0.0, false)? {
+                Some(source_account) => {
+                    accounts.push(source_account);
+                    LedgerAccount::ClientAvailable(disputed_transaction.client_id)
+                }
+                None => return Ok(None),
+            }
+        } else {
+            LedgerAccount::ChargebackLoss
+        };
+        // the fee is refunded by the house, as the transaction it was charged on is reversed
+        match self.updated_house_account(&disputed_transaction, -disputed_transaction.fee)? {
+            Some(house_account) => accounts.extend(house_account),
+            None => return Ok(None),
+        }
+        let currency = disputed_transaction.account_currency();
+        let fee = disputed_transaction.fee as f64;
+        let journal_entry = JournalEntry::new(
+            transaction,
+            vec![
+                Posting::new(
+                    LedgerAccount::ClientHeld(holder_client_id),
+                    currency,
+                    -disputed_amount as f64,
+                ),
+                Posting::new(LedgerAccount::Fees, currency, -fee),
+                Posting::new(reversal_account, currency, disputed_amount as f64 + fee),
+            ],
+        );
+        let risk_counters =
+            self.updated_risk_counters(&disputed_transaction, -1, disputed_amount.abs())?;
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            risk_counters: vec![risk_counters],
+            ..LedgerUpdate::new(
+                accounts,
+                vec![Transaction {
+                    under_dispute: false,
+                    ..disputed_transaction
+                }],
+            )
+        }))
+    }
+    fn execute_set_lock(
+        &mut self,
+        transaction: Transaction,
+        locked: bool,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        let mut accounts = self.store.client_accounts(transaction.client_id)?;
+        if accounts.is_empty() {
+            self.transaction_can_not_be_performed_error(&transaction, "account does not exist");
+            return Ok(None);
+        }
+        for account in &mut accounts {
+            account.locked = locked;
+            account.lock_reason = None;
         }
-        self.set_transaction_under_dispute(&disputed_transaction, false);
+        // admin transactions are stored as well, so every lock change stays auditable
+        Ok(Some(LedgerUpdate::new(accounts, vec![transaction])))
+    }
+    fn execute_adjustment(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<LedgerUpdate>> {
+        let account = match self.updated_account(&transaction, transaction.amount, 0.0, false)? {
+            Some(account) => account,
+            None => return Ok(None),
+        };
+        let currency = transaction.account_currency();
+        let journal_entry = JournalEntry::new(
+            transaction,
+            vec![
+                Posting::new(
+                    LedgerAccount::ClientAvailable(transaction.client_id),
+                    currency,
+                    transaction.amount as f64,
+                ),
+                Posting::new(
+                    LedgerAccount::Adjustments,
+                    currency,
+                    -transaction.amount as f64,
+                ),
+            ],
+        );
+        Ok(Some(LedgerUpdate {
+            journal: vec![journal_entry],
+            ..LedgerUpdate::new(vec![account], vec![transaction])
+        }))
+    }
+    // moves the given amount of the disputed transaction from available to held funds
+    fn held_funds_postings(&self, disputed_transaction: &Transaction, amount: f32) -> Vec<Posting> {
+        let currency = disputed_transaction.account_currency();
+        let client_id = TransactionProcessor::disputed_client_id(disputed_transaction);
+        vec![
+            Posting::new(
+                LedgerAccount::ClientAvailable(client_id),
+                currency,
+                -amount as f64,
+            ),
+            Posting::new(
+                LedgerAccount::ClientHeld(client_id),
+                currency,
+                amount as f64,
+            ),
+        ]
+    }
+    fn involves_client(transaction: &Transaction, client_id: ClientId) -> bool {
+        transaction.client_id == client_id || transaction.destination_client_id == Some(client_id)
+    }
+    // the client holding the funds of a disputed transaction, the receiving one for transfers
+    fn disputed_client_id(disputed_transaction: &Transaction) -> ClientId {
+        disputed_transaction
+            .destination_client_id
+            .unwrap_or(disputed_transaction.client_id)
     }
 
-    fn account_is_locked(&mut self, client_id: u16) -> bool {
-        match self.account_map.get(&client_id) {
-            None => false,
-            Some(&account) => account.locked,
+    fn account_is_locked(&self, client_id: ClientId) -> anyhow::Result<bool> {
+        Ok(self
+            .store
+            .client_accounts(client_id)?
+            .iter()
+            .any(|account| account.locked))
+    }
+    fn account_has_sufficient_funds(&mut self, transaction: &Transaction) -> anyhow::Result<bool> {
+        // an account that does not exist yet has no funds
+        let available = match self
+            .store
+            .get_account(transaction.client_id, transaction.account_currency())?
+        {
+            Some(account) => account.available,
+            None => 0.0,
+        };
+        let overdraft_limit = self
+            .client_limits
+            .get(transaction.client_id, transaction.account_currency())
+            .overdraft_limit;
+        if available + overdraft_limit < transaction.account_amount() + transaction.fee {
+            let reason = if overdraft_limit > 0.0 {
+                "overdraft limit exceeded"
+            } else {
+                "insufficient funds"
+            };
+            self.transaction_can_not_be_performed_error(transaction, reason);
+            return Ok(false);
         }
+        Ok(true)
     }
-    fn account_has_sufficient_funds(&mut self, transaction: &Transaction) -> bool {
-        // the account might not be created yet
-        self.insert_or_update_account(&transaction, 0.0, 0.0, false);
-        if self.account_map[&transaction.client_id].available < transaction.amount {
-            self.transaction_can_not_be_performed_error(&transaction, "insufficient funds");
-            return false;
+    // Runs the checks shared by withdrawals and authorizations and returns the transaction
+    // with its conversion and fee set, together with the day's updated withdrawal total.
+    fn checked_withdrawal(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<(Transaction, DailyWithdrawal)>> {
+        let transaction = match self.converted_withdrawal(transaction)? {
+            Some(transaction) => transaction,
+            None => return Ok(None),
+        };
+        let transaction = Transaction {
+            fee: self
+                .fee_schedule
+                .fee(TransactionType::Withdrawal, transaction.account_amount()),
+            ..transaction
+        };
+        let daily_withdrawal = match self.updated_daily_withdrawal(&transaction)? {
+            Some(daily_withdrawal) => daily_withdrawal,
+            None => return Ok(None),
+        };
+        if !self.account_has_sufficient_funds(&transaction)? {
+            return Ok(None);
         }
-        true
+        Ok(Some((transaction, daily_withdrawal)))
     }
-    fn insert_or_update_account(
+    // Checks the single and daily withdrawal caps of the account and returns the day's
+    // withdrawn total including this withdrawal, or None when a cap would be exceeded.
+    fn updated_daily_withdrawal(
         &mut self,
         transaction: &Transaction,
-        available: f32,
-        held: f32,
-        locked: bool,
-    ) -> bool {
-        match self.account_map.get_mut(&transaction.client_id) {
-            Some(account) => {
-                if account.available + available < MIN_FUNDS
-                    || account.available + available > MAX_FUNDS
-                    || account.held + held < MIN_FUNDS
-                    || account.held + held > MAX_FUNDS
-                {
-                    self.transaction_can_not_be_performed_error(
-                        transaction,
-                        "your accounts will be out of bounds",
-                    );
-                    return false;
-                }
-                account.available += available;
-                account.held += held;
-                // a bit tricky, but nice and compact :)
-                account.locked = account.locked || locked;
-                true
-            }
+    ) -> anyhow::Result<Option<DailyWithdrawal>> {
+        let currency = transaction.account_currency();
+        let limits = self.client_limits.get(transaction.client_id, currency);
+        let amount = transaction.account_amount();
+        if limits
+            .max_withdrawal
+            .is_some_and(|max_withdrawal| amount > max_withdrawal)
+        {
+            self.transaction_can_not_be_performed_error(
+                transaction,
+                "withdrawal exceeds the maximum single withdrawal",
+            );
+            return Ok(None);
+        }
+        let date = self.business_date();
+        let withdrawn = self
+            .store
+            .get_daily_withdrawal(transaction.client_id, currency, date)?
+            + amount;
+        if limits
+            .daily_withdrawal_cap
+            .is_some_and(|daily_withdrawal_cap| withdrawn > daily_withdrawal_cap)
+        {
+            self.transaction_can_not_be_performed_error(
+                transaction,
+                "daily withdrawal cap exceeded",
+            );
+            return Ok(None);
+        }
+        Ok(Some(DailyWithdrawal {
+            client_id: transaction.client_id,
+            currency,
+            date,
+            amount: withdrawn,
+        }))
+    }
+    // day used for scheduling, fx rates and daily limits
+    fn business_date(&self) -> NaiveDate {
+        self.clock.unwrap_or_else(|| Local::now().date_naive())
+    }
+    // A withdrawal in a currency the client does not hold is paid out of one of its
+    // other accounts, preferring the first one that can cover the converted amount.
+    // The applied rate is kept on the transaction so a dispute reverses it exactly.
+    fn converted_withdrawal(
+        &mut self,
+        transaction: Transaction,
+    ) -> anyhow::Result<Option<Transaction>> {
+        let currency = transaction.resolved_currency();
+        let mut accounts = self.store.client_accounts(transaction.client_id)?;
+        if accounts.is_empty() || accounts.iter().any(|account| account.currency == currency) {
+            return Ok(Some(transaction));
+        }
+        accounts.sort_by_key(|account| account.currency);
+        let today = self.business_date();
+        let candidates: Vec<Transaction> = accounts
+            .iter()
+            .filter_map(|account| {
+                let rate = self.fx_rates.rate(currency, account.currency, today)?;
+                Some(Transaction {
+                    fx_conversion: Some(FxConversion {
+                        account_currency: account.currency,
+                        rate,
+                    }),
+                    ..transaction
+                })
+            })
+            .collect();
+        let funded = candidates.iter().find(|candidate| {
+            accounts.iter().any(|account| {
+                account.currency == candidate.account_currency()
+                    && account.available >= candidate.account_amount()
+            })
+        });
+        match funded.or_else(|| candidates.first()) {
+            Some(candidate) => Ok(Some(*candidate)),
             None => {
-                self.account_map.insert(
-                    transaction.client_id,
-                    Account::new(transaction.client_id, available, held, locked),
+                self.transaction_can_not_be_performed_error(
+                    &transaction,
+                    "no fx rate to convert from a currency the client holds",
                 );
-                true
+                Ok(None)
             }
         }
     }
-    fn insert_transaction(&mut self, transaction: Transaction) {
-        self.transaction_map.insert(transaction.id, transaction);
+    // Computes the account state after applying the given deltas without persisting it,
+    // so the caller can commit it together with the rest of the transaction's effect.
+    fn updated_account(
+        &mut self,
+        transaction: &Transaction,
+        available: f32,
+        held: f32,
+        locked: bool,
+    ) -> anyhow::Result<Option<Account>> {
+        self.updated_client_account(transaction, transaction.client_id, available, held, locked)
     }
-    fn set_transaction_under_dispute(&mut self, transaction: &Transaction, under_dispute: bool) {
-        match self.transaction_map.get_mut(&transaction.id) {
-            Some(transaction) => {
-                transaction.under_dispute = under_dispute;
-            }
-            None => panic!("internal server error"),
+    // Fee income of the house in the transaction's account currency. Returns an empty
+    // list when there is no fee to book, and None when the booking is rejected.
+    fn updated_house_account(
+        &mut self,
+        transaction: &Transaction,
+        fee: f32,
+    ) -> anyhow::Result<Option<Vec<Account>>> {
+        if fee == 0.0 {
+            return Ok(Some(vec![]));
         }
+        let house_client_id = self
+            .fee_schedule
+            .house_client_id
+            .ok_or_else(|| anyhow!("fee of {} charged without a fee account", fee))?;
+        Ok(self
+            .updated_client_account(transaction, house_client_id, fee, 0.0, false)?
+            .map(|account| vec![account]))
     }
-    fn transaction_exists_and_not_under_dispute(&self, transaction: &Transaction) -> bool {
-        if !(self.transaction_map.contains_key(&transaction.id)
-            && !self.transaction_map[&transaction.id].under_dispute)
+    fn updated_client_account(
+        &mut self,
+        transaction: &Transaction,
+        client_id: ClientId,
+        available: f32,
+        held: f32,
+        locked: bool,
+    ) -> anyhow::Result<Option<Account>> {
+        let currency = transaction.account_currency();
+        let mut account = match self.store.get_account(client_id, currency)? {
+            Some(account) => account,
+            None => {
+                // a new currency account inherits the lock of the client's other accounts
+                let mut account = Account::new_with_client(client_id, currency);
+                account.locked = self.account_is_locked(client_id)?;
+                account
+            }
+        };
+        if account.available + available < MIN_FUNDS
+            || account.available + available > MAX_FUNDS
+            || account.held + held < MIN_FUNDS
+            || account.held + held > MAX_FUNDS
         {
             self.transaction_can_not_be_performed_error(
-                &transaction,
-                "transaction does not exist or is already under dispute",
+                transaction,
+                "your accounts will be out of bounds",
             );
-            return false;
+            return Ok(None);
         }
-        true
+        account.available += available;
+        account.held += held;
+        // a bit tricky, but nice and compact :)
+        account.locked = account.locked || locked;
+        Ok(Some(account))
     }
-    fn transaction_exists_and_under_dispute(&self, transaction: &Transaction) -> bool {
-        if !(self.transaction_map.contains_key(&transaction.id)
-            && self.transaction_map[&transaction.id].under_dispute)
+    // Dispute counters of the account holding the disputed funds after opening (1) or
+    // closing (-1) a dispute on it, with the charged back amount added
+    fn updated_risk_counters(
+        &self,
+        disputed_transaction: &Transaction,
+        open_disputes: i32,
+        chargeback_amount: f32,
+    ) -> anyhow::Result<RiskCounters> {
+        let client_id = TransactionProcessor::disputed_client_id(disputed_transaction);
+        let currency = disputed_transaction.account_currency();
+        let mut risk_counters = self
+            .store
+            .client_risk_counters(client_id)?
+            .into_iter()
+            .find(|risk_counters| risk_counters.currency == currency)
+            .unwrap_or_else(|| RiskCounters::new(client_id, currency));
+        risk_counters.open_disputes = risk_counters
+            .open_disputes
+            .saturating_add_signed(open_disputes);
+        risk_counters.chargeback_amount += chargeback_amount;
+        Ok(risk_counters)
+    }
+    // Locks all accounts of the client when its state after the update triggers a lock
+    // policy. A client the update locks anyway, as a chargeback does, gets no lock reason.
+    fn apply_lock_policies(
+        &self,
+        client_id: ClientId,
+        update: &mut LedgerUpdate,
+    ) -> anyhow::Result<()> {
+        let mut accounts = self.store.client_accounts(client_id)?;
+        for updated in update
+            .accounts
+            .iter()
+            .filter(|updated| updated.client_id == client_id)
         {
-            self.transaction_can_not_be_performed_error(
-                &transaction,
-                "transaction does not exist or is not under dispute",
-            );
-            return false;
+            match accounts
+                .iter_mut()
+                .find(|account| account.currency == updated.currency)
+            {
+                Some(account) => *account = *updated,
+                None => accounts.push(*updated),
+            }
+        }
+        if accounts.iter().any(|account| account.locked) {
+            return Ok(());
+        }
+        let mut risk_counters = self.store.client_risk_counters(client_id)?;
+        for updated in update
+            .risk_counters
+            .iter()
+            .filter(|updated| updated.client_id == client_id)
+        {
+            match risk_counters
+                .iter_mut()
+                .find(|counters| counters.currency == updated.currency)
+            {
+                Some(counters) => *counters = *updated,
+                None => risk_counters.push(*updated),
+            }
+        }
+        let policy =
+            match self
+                .lock_policies
+                .triggered(&accounts, &risk_counters, &self.client_limits)
+            {
+                Some(policy) => policy,
+                None => return Ok(()),
+            };
+        error!("Client {} locked by policy: {}", client_id, policy);
+        for account in accounts {
+            let account = Account {
+                locked: true,
+                lock_reason: Some(policy),
+                ..account
+            };
+            match update.accounts.iter_mut().find(|updated| {
+                updated.client_id == client_id && updated.currency == account.currency
+            }) {
+                Some(updated) => *updated = account,
+                None => update.accounts.push(account),
+            }
+        }
+        Ok(())
+    }
+    // the client's accounts in currencies other than the given account's, with the lock set
+    fn other_client_accounts_with_lock(
+        &self,
+        account: &Account,
+        locked: bool,
+    ) -> anyhow::Result<Vec<Account>> {
+        Ok(self
+            .store
+            .client_accounts(account.client_id)?
+            .into_iter()
+            .filter(|other| other.currency != account.currency)
+            .map(|other| Account { locked, ..other })
+            .collect())
+    }
+    fn get_stored_transaction(&self, id: TransactionId) -> anyhow::Result<Transaction> {
+        self.store
+            .get_transaction(id)?
+            .ok_or_else(|| anyhow!("transaction {} is missing from the ledger store", id))
+    }
+    fn transaction_exists_and_not_under_dispute(
+        &mut self,
+        transaction: &Transaction,
+    ) -> anyhow::Result<bool> {
+        match self.store.get_transaction(transaction.id)? {
+            Some(stored_transaction) if !stored_transaction.under_dispute => Ok(true),
+            _ => {
+                self.transaction_can_not_be_performed_error(
+                    transaction,
+                    "transaction does not exist or is already under dispute",
+                );
+                Ok(false)
+            }
+        }
+    }
+    fn transaction_exists_and_under_dispute(
+        &mut self,
+        transaction: &Transaction,
+    ) -> anyhow::Result<bool> {
+        match self.store.get_transaction(transaction.id)? {
+            Some(stored_transaction) if stored_transaction.under_dispute => Ok(true),
+            _ => {
+                self.transaction_can_not_be_performed_error(
+                    transaction,
+                    "transaction does not exist or is not under dispute",
+                );
+                Ok(false)
+            }
+        }
+    }
+    // The transaction a release refers to, if a rule held it and it was not accepted since
+    fn held_transaction(
+        &mut self,
+        transaction: &Transaction,
+    ) -> anyhow::Result<Option<Transaction>> {
+        // a dispute is held under the id of the deposit it disputes, so the latest entry that
+        // holds the id is looked for, unless an accepted release of it comes first
+        let held_transaction = self
+            .store
+            .client_history(transaction.client_id)?
+            .into_iter()
+            .rev()
+            .filter(|entry| entry.transaction.id == transaction.id)
+            .find(|entry| {
+                entry.held
+                    || (entry.accepted && entry.transaction.of_type == TransactionType::Release)
+            })
+            .filter(|entry| entry.held)
+            .map(|entry| entry.transaction);
+        match held_transaction {
+            Some(held_transaction) => Ok(Some(held_transaction)),
+            None => {
+                self.transaction_can_not_be_performed_error(
+                    transaction,
+                    "transaction does not exist or is not held",
+                );
+                Ok(None)
+            }
+        }
+    }
+    // the stored authorization a capture or void refers to, if it is still pending
+    fn pending_authorization(
+        &mut self,
+        transaction: &Transaction,
+    ) -> anyhow::Result<Option<Transaction>> {
+        let authorization = match self.store.get_transaction(transaction.id)? {
+            Some(stored_transaction)
+                if stored_transaction.of_type == TransactionType::Authorize =>
+            {
+                stored_transaction
+            }
+            _ => {
+                self.transaction_can_not_be_performed_error(
+                    transaction,
+                    "transaction does not exist or is not a pending authorization",
+                );
+                return Ok(None);
+            }
+        };
+        if !self.transactions_reference_the_same_client(transaction, &authorization) {
+            return Ok(None);
+        }
+        if !self.transactions_reference_the_same_currency(transaction, &authorization) {
+            return Ok(None);
+        }
+        Ok(Some(authorization))
+    }
+    fn transaction_is_disputable(
+        &mut self,
+        current_transaction: &Transaction,
+        referenced_transaction: &Transaction,
+    ) -> bool {
+        match referenced_transaction.of_type {
+            TransactionType::Deposit | TransactionType::Withdrawal | TransactionType::Transfer => {
+                true
+            }
+            _ => {
+                self.transaction_can_not_be_performed_error(
+                    current_transaction,
+                    "only deposits, withdrawals and transfers can be disputed",
+                );
+                false
+            }
         }
-        true
     }
     fn get_disputed_amount_from_transaction(&self, transaction: &Transaction) -> f32 {
         // The assumption is amount disputed on Dispute of Withdrawal is negative the
         // amount on transaction. Check README for more details.
+        // The fee is included, the disputed amount is what the client's balance changed by.
         match transaction.of_type {
-            TransactionType::Deposit => transaction.amount,
-            TransactionType::Withdrawal => -transaction.amount,
+            TransactionType::Deposit => transaction.account_amount() - transaction.fee,
+            TransactionType::Withdrawal => -(transaction.account_amount() + transaction.fee),
+            // held on the receiving client, see `disputed_client_id`
+            TransactionType::Transfer => transaction.amount,
             _ => panic!("internal server error"),
         }
     }
     fn transactions_reference_the_same_client(
-        &self,
+        &mut self,
         current_transaction: &Transaction,
         referenced_transaction: &Transaction,
     ) -> bool {
@@ -239,7 +1794,28 @@ impl TransactionProcessor {
         }
         true
     }
-    fn transaction_can_not_be_performed_error(&self, transaction: &Transaction, message: &str) {
+    fn transactions_reference_the_same_currency(
+        &mut self,
+        current_transaction: &Transaction,
+        referenced_transaction: &Transaction,
+    ) -> bool {
+        match current_transaction.currency {
+            Some(currency) if currency != referenced_transaction.resolved_currency() => {
+                self.transaction_can_not_be_performed_error(
+                    current_transaction,
+                    "currencies of current and referenced transactions do not match",
+                );
+                false
+            }
+            _ => true,
+        }
+    }
+    fn transaction_can_not_be_performed_error(
+        &mut self,
+        transaction: &Transaction,
+        message: &'static str,
+    ) {
+        self.rejection = Some(message);
         error!(
             "Transaction: {:?} can not be performed. Reason: {}",
             transaction, message
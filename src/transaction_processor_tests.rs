@@ -1,348 +1,180 @@
 #![cfg(test)]
 mod tests {
     use crate::account::Account;
-    use crate::transaction::{Transaction, TransactionType};
+    use crate::client_limits::{ClientLimits, ClientLimitsTable};
+    use crate::currency::Currency;
+    use crate::error_budget::{Checkpoint, ErrorBudget, ErrorCounts};
+    use crate::fee_schedule::{FeeSchedule, FeeTier};
+    use crate::fx_rates::FxRates;
+    use crate::history::HistoryEntry;
+    use crate::interest::{interest_transaction_id, InterestRates};
+    use crate::journal::{JournalEntry, LedgerAccount};
+    use crate::ledger_store::{
+        InMemoryLedgerStore, LedgerStore, LedgerUpdate, ScheduledTransaction,
+    };
+    use crate::lock_policies::{LockPolicies, LockPolicy, RiskCounters};
+    use crate::metrics::Metrics;
+    use crate::rules::{Rule, RuleAction, RuleKind, RulesEngine};
+    use crate::run_summary::RunSummary;
+    use crate::test_helpers::{
+        adjustment, authorize, capture, chargeback, create_transaction_receiver, deposit, dispute,
+        lock, resolve, transfer, unlock, void, withdrawal,
+    };
+    use crate::transaction::{ClientId, FxConversion, Transaction, TransactionId, TransactionType};
     use crate::transaction_processor::TransactionProcessor;
-    use anyhow;
-    use maplit::hashmap;
+    use anyhow::anyhow;
+    use chrono::NaiveDate;
+    use maplit::{btreemap, hashmap};
     use tokio::sync::mpsc;
 
-    fn create_transaction_receiver(
-        transactions: Vec<Transaction>,
-    ) -> mpsc::Receiver<anyhow::Result<Transaction>> {
-        let (sender, receiver) = mpsc::channel(1);
-        tokio::spawn(async move {
-            for transaction in transactions {
-                sender.send(Ok(transaction)).await.unwrap();
-            }
-        });
-        receiver
-    }
     #[tokio::test]
     async fn test_deposit() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 2,
-                of_type: TransactionType::Deposit,
-                client_id: 2,
-                amount: 5.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 3,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 5.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> =
+            vec![deposit(1, 1, 10.0), deposit(2, 2, 5.0), deposit(3, 1, 5.0)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
         assert_eq!(
             hashmap! {
-                1 => Account::new(1, 15.0, 0.0, false),
-                2 => Account::new(2, 5.0, 0.0, false)
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 15.0, 0.0, false),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 5.0, 0.0, false)
             },
             accounts
         );
     }
     #[tokio::test]
     async fn test_withdrawal() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 2,
-                of_type: TransactionType::Withdrawal,
-                client_id: 1,
-                amount: 5.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> = vec![deposit(1, 1, 10.0), withdrawal(2, 1, 5.0)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 5.0, 0.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 5.0, 0.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_over_withdrawal() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 2,
-                of_type: TransactionType::Withdrawal,
-                client_id: 1,
-                amount: 15.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> = vec![deposit(1, 1, 10.0), withdrawal(2, 1, 15.0)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 10.0, 0.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_dispute() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> = vec![deposit(1, 1, 10.0), dispute(1, 1)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 0.0, 10.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 0.0, 10.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_dispute_disputed() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> =
+            vec![deposit(1, 1, 10.0), dispute(1, 1), dispute(1, 1)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 0.0, 10.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 0.0, 10.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_resolve_undisputed() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Resolve,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> = vec![deposit(1, 1, 10.0), resolve(1, 1)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 10.0, 0.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_chargeback_undisputed() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Chargeback,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> = vec![deposit(1, 1, 10.0), chargeback(1, 1)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 10.0, 0.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_resolve_disputed() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Resolve,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> =
+            vec![deposit(1, 1, 10.0), dispute(1, 1), resolve(1, 1)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 10.0, 0.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_chargeback_disputed() {
-        let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Chargeback,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-        ];
+        let transactions: Vec<Transaction> =
+            vec![deposit(1, 1, 10.0), dispute(1, 1), chargeback(1, 1)];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 0.0, 0.0, true)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 0.0, 0.0, true)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_transaction_on_frozen_account() {
         let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Chargeback,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 2,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
+            deposit(1, 1, 10.0),
+            dispute(1, 1),
+            chargeback(1, 1),
+            deposit(2, 1, 10.0),
         ];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 0.0, 0.0, true)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 0.0, 0.0, true)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_transaction_on_resolved_account() {
         let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Resolve,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 2,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
+            deposit(1, 1, 10.0),
+            dispute(1, 1),
+            resolve(1, 1),
+            deposit(2, 1, 10.0),
         ];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 20.0, 0.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 20.0, 0.0, false)},
+            accounts
+        );
     }
     /**
     * example from README
@@ -358,172 +190,1558 @@ mod tests {
     #[tokio::test]
     async fn test_dispute_withdrawal_and_resolve() {
         let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 2,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 100.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 3,
-                of_type: TransactionType::Withdrawal,
-                client_id: 1,
-                amount: 50.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 3,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 4,
-                of_type: TransactionType::Withdrawal,
-                client_id: 1,
-                amount: 110.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 3,
-                of_type: TransactionType::Resolve,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
+            deposit(1, 1, 10.0),
+            deposit(2, 1, 100.0),
+            withdrawal(3, 1, 50.0),
+            dispute(3, 1),
+            withdrawal(4, 1, 110.0),
+            resolve(3, 1),
         ];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, -50.0, 0.0, false)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, -50.0, 0.0, false)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_dispute_withdrawal_and_chargeback() {
         let transactions: Vec<Transaction> = vec![
-            Transaction {
-                id: 1,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 10.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 2,
-                of_type: TransactionType::Deposit,
-                client_id: 1,
-                amount: 100.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 3,
-                of_type: TransactionType::Withdrawal,
-                client_id: 1,
-                amount: 50.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 3,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 4,
-                of_type: TransactionType::Withdrawal,
-                client_id: 1,
-                amount: 110.0,
-                under_dispute: false,
-            },
-            Transaction {
-                id: 3,
-                of_type: TransactionType::Chargeback,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            },
+            deposit(1, 1, 10.0),
+            deposit(2, 1, 100.0),
+            withdrawal(3, 1, 50.0),
+            dispute(3, 1),
+            withdrawal(4, 1, 110.0),
+            chargeback(3, 1),
         ];
         let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
             .get_account_map();
-        assert_eq!(hashmap! {1 => Account::new(1, 0.0, 0.0, true)}, accounts);
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 0.0, 0.0, true)},
+            accounts
+        );
     }
     #[tokio::test]
     async fn test_dispute_non_existent_transactions() {
         assert_eq!(
             hashmap! {},
-            TransactionProcessor::new(create_transaction_receiver(vec![Transaction {
-                id: 1,
-                of_type: TransactionType::Dispute,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            }]))
+            TransactionProcessor::new(create_transaction_receiver(vec![dispute(1, 1)]))
+                .execute()
+                .await
+                .get_account_map()
+        );
+        assert_eq!(
+            hashmap! {},
+            TransactionProcessor::new(create_transaction_receiver(vec![resolve(1, 1)]))
+                .execute()
+                .await
+                .get_account_map()
+        );
+        assert_eq!(
+            hashmap! {},
+            TransactionProcessor::new(create_transaction_receiver(vec![chargeback(1, 1)]))
+                .execute()
+                .await
+                .get_account_map()
+        );
+    }
+    #[tokio::test]
+    async fn test_out_of_bounds_amounts() {
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            deposit(1, 1, 999_999_999.0),
+            deposit(2, 1, 1000.0),
+        ]));
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 999_999_999.0, 0.0, false)},
+            processor.get_account_map()
+        );
+        assert_eq!(
+            1,
+            processor
+                .get_outcomes()
+                .count("rejected", Some("your accounts will be out of bounds"))
+        );
+    }
+    #[tokio::test]
+    async fn test_rejected_withdrawal_creates_no_account() {
+        let mut processor =
+            TransactionProcessor::new(create_transaction_receiver(vec![withdrawal(1, 1, 5.0)]));
+        let processor = processor.execute().await;
+        assert_eq!(hashmap! {}, processor.get_account_map());
+        assert_eq!(None, processor.get_store().get_transaction(1).unwrap());
+    }
+    #[tokio::test]
+    async fn test_rejected_withdrawal_is_not_recorded() {
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            deposit(1, 1, 10.0),
+            withdrawal(2, 1, 15.0),
+            dispute(2, 1),
+        ]));
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)},
+            processor.get_account_map()
+        );
+        assert_eq!(None, processor.get_store().get_transaction(2).unwrap());
+    }
+    #[tokio::test]
+    async fn test_rejected_dispute_leaves_no_partial_update() {
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            deposit(1, 1, 1_000_000_000.0),
+            withdrawal(2, 1, 1_000_000_000.0),
+            deposit(3, 1, 1_000_000_000.0),
+            // reversing the withdrawal would push available above the upper bound
+            dispute(2, 1),
+        ]));
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 1_000_000_000.0, 0.0, false)},
+            processor.get_account_map()
+        );
+        assert_eq!(
+            Some(withdrawal(2, 1, 1_000_000_000.0)),
+            processor.get_store().get_transaction(2).unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn test_unlock_after_chargeback() {
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 10.0),
+            deposit(2, 1, 5.0),
+            dispute(2, 1),
+            chargeback(2, 1),
+            unlock(3, 1),
+            deposit(4, 1, 1.0),
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions));
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 11.0, 0.0, false)},
+            processor.get_account_map()
+        );
+        assert_eq!(
+            Some(TransactionType::Unlock),
+            processor
+                .get_store()
+                .get_transaction(3)
+                .unwrap()
+                .map(|transaction| transaction.of_type)
+        );
+    }
+    #[tokio::test]
+    async fn test_lock() {
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 10.0),
+            lock(2, 1),
+            withdrawal(3, 1, 5.0),
+            lock(4, 2),
+        ];
+        let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
-            .get_account_map()
+            .get_account_map();
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, true)},
+            accounts
         );
+    }
+    #[tokio::test]
+    async fn test_adjustment() {
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 10.0),
+            lock(2, 1),
+            adjustment(3, 1, -2.5, 7),
+            adjustment(4, 2, 3.0, 1),
+        ];
+        let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .execute()
+            .await
+            .get_account_map();
         assert_eq!(
-            hashmap! {},
-            TransactionProcessor::new(create_transaction_receiver(vec![Transaction {
-                id: 1,
-                of_type: TransactionType::Resolve,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            }]))
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 7.5, 0.0, true),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 3.0, 0.0, false)
+            },
+            accounts
+        );
+    }
+    #[tokio::test]
+    async fn test_dispute_adjustment() {
+        let transactions: Vec<Transaction> = vec![adjustment(1, 1, 3.0, 1), dispute(1, 1)];
+        let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
-            .get_account_map()
+            .get_account_map();
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 3.0, 0.0, false)},
+            accounts
         );
+    }
+    #[tokio::test]
+    async fn test_client_history() {
+        let first_deposit = deposit(1, 1, 10.0);
+        let over_withdrawal = withdrawal(2, 1, 15.0);
+        let dispute = dispute(1, 1);
+        let chargeback = chargeback(1, 1);
+        let other_client_deposit = deposit(3, 2, 5.0);
+        let late_deposit = deposit(4, 1, 5.0);
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            first_deposit,
+            over_withdrawal,
+            dispute,
+            chargeback,
+            other_client_deposit,
+            late_deposit,
+        ]));
+        let processor = processor.execute().await;
         assert_eq!(
-            hashmap! {},
-            TransactionProcessor::new(create_transaction_receiver(vec![Transaction {
-                id: 1,
-                of_type: TransactionType::Chargeback,
-                client_id: 1,
-                amount: 0.0,
-                under_dispute: false,
-            }]))
+            vec![
+                HistoryEntry::accepted(
+                    first_deposit,
+                    Account::new(1, Currency::Eur, 10.0, 0.0, false)
+                ),
+                HistoryEntry::rejected(
+                    over_withdrawal,
+                    Account::new(1, Currency::Eur, 10.0, 0.0, false),
+                    "insufficient funds"
+                ),
+                HistoryEntry::accepted(dispute, Account::new(1, Currency::Eur, 0.0, 10.0, false)),
+                HistoryEntry::accepted(chargeback, Account::new(1, Currency::Eur, 0.0, 0.0, true)),
+                HistoryEntry::rejected(
+                    late_deposit,
+                    Account::new(1, Currency::Eur, 0.0, 0.0, true),
+                    "account is locked"
+                ),
+            ],
+            processor.get_store().client_history(1).unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn test_multi_currency() {
+        let transactions: Vec<Transaction> = vec![
+            Transaction {
+                currency: Some(Currency::Eur),
+                ..deposit(1, 1, 10.0)
+            },
+            Transaction {
+                currency: Some(Currency::Usd),
+                ..deposit(2, 1, 5.0)
+            },
+            // EUR funds do not cover a USD withdrawal
+            Transaction {
+                currency: Some(Currency::Usd),
+                ..withdrawal(3, 1, 6.0)
+            },
+            Transaction {
+                currency: Some(Currency::Usd),
+                ..withdrawal(4, 1, 2.0)
+            },
+            Transaction {
+                currency: Some(Currency::Gbp),
+                ..deposit(5, 2, 7.0)
+            },
+        ];
+        let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
             .execute()
             .await
-            .get_account_map()
+            .get_account_map();
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false),
+                (1, Currency::Usd) => Account::new(1, Currency::Usd, 3.0, 0.0, false),
+                (2, Currency::Gbp) => Account::new(2, Currency::Gbp, 7.0, 0.0, false)
+            },
+            accounts
         );
     }
     #[tokio::test]
-    async fn test_out_of_bounds_amounts() {
+    async fn test_dispute_currency_mismatch() {
+        let transactions: Vec<Transaction> = vec![
+            Transaction {
+                currency: Some(Currency::Usd),
+                ..deposit(1, 1, 10.0)
+            },
+            Transaction {
+                currency: Some(Currency::Eur),
+                ..dispute(1, 1)
+            },
+        ];
+        let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .execute()
+            .await
+            .get_account_map();
         assert_eq!(
-            hashmap! {1 => Account::new(1, 999_999_999.0, 0.0, false)},
-            TransactionProcessor::new(create_transaction_receiver(vec![
+            hashmap! {(1, Currency::Usd) => Account::new(1, Currency::Usd, 10.0, 0.0, false)},
+            accounts
+        );
+    }
+    #[tokio::test]
+    async fn test_chargeback_locks_all_currencies() {
+        let transactions: Vec<Transaction> = vec![
+            Transaction {
+                currency: Some(Currency::Eur),
+                ..deposit(1, 1, 10.0)
+            },
+            Transaction {
+                currency: Some(Currency::Usd),
+                ..deposit(2, 1, 5.0)
+            },
+            // the dispute takes the currency of the disputed transaction
+            dispute(2, 1),
+            Transaction {
+                currency: Some(Currency::Usd),
+                ..chargeback(2, 1)
+            },
+            Transaction {
+                currency: Some(Currency::Eur),
+                ..deposit(3, 1, 1.0)
+            },
+        ];
+        let accounts = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .execute()
+            .await
+            .get_account_map();
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, true),
+                (1, Currency::Usd) => Account::new(1, Currency::Usd, 0.0, 0.0, true)
+            },
+            accounts
+        );
+    }
+    #[tokio::test]
+    async fn test_withdrawal_with_fx_conversion() {
+        let mut fx_rates = FxRates::new();
+        fx_rates.insert(
+            Currency::Eur,
+            Currency::Usd,
+            NaiveDate::from_ymd_opt(2020, 1, 1).unwrap(),
+            1.25,
+        );
+        let transactions: Vec<Transaction> = vec![
+            Transaction {
+                currency: Some(Currency::Eur),
+                ..deposit(1, 1, 100.0)
+            },
+            Transaction {
+                currency: Some(Currency::Usd),
+                ..withdrawal(2, 1, 10.0)
+            },
+            // there is no rate to pay GBP out of EUR
+            Transaction {
+                currency: Some(Currency::Gbp),
+                ..withdrawal(3, 1, 10.0)
+            },
+            dispute(2, 1),
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .with_fx_rates(fx_rates);
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {(1, Currency::Eur) => Account::new(1, Currency::Eur, 100.0, -8.0, false)},
+            processor.get_account_map()
+        );
+        assert_eq!(
+            Some(Transaction {
+                under_dispute: true,
+                currency: Some(Currency::Usd),
+                fx_conversion: Some(FxConversion {
+                    account_currency: Currency::Eur,
+                    rate: 1.0 / 1.25,
+                }),
+                ..withdrawal(2, 1, 10.0)
+            }),
+            processor.get_store().get_transaction(2).unwrap()
+        );
+        assert_eq!(None, processor.get_store().get_transaction(3).unwrap());
+    }
+    #[tokio::test]
+    async fn test_fees() {
+        let mut fee_schedule = FeeSchedule::new();
+        fee_schedule.house_client_id = Some(0);
+        fee_schedule.insert(
+            TransactionType::Deposit,
+            FeeTier {
+                from_amount: 0.0,
+                flat: 1.0,
+                percentage: 0.0,
+            },
+        );
+        fee_schedule.insert(
+            TransactionType::Withdrawal,
+            FeeTier {
+                from_amount: 0.0,
+                flat: 0.0,
+                percentage: 10.0,
+            },
+        );
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 100.0),
+            withdrawal(2, 1, 10.0),
+            // the fee makes the withdrawal exceed the available funds
+            withdrawal(3, 1, 90.0),
+            dispute(1, 1),
+            chargeback(1, 1),
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .with_fee_schedule(fee_schedule)
+            .unwrap();
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (0, Currency::Eur) => Account::new(0, Currency::Eur, 1.0, 0.0, false),
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, -11.0, 0.0, true)
+            },
+            processor.get_account_map()
+        );
+        assert_eq!(
+            Some(1.0),
+            processor
+                .get_store()
+                .get_transaction(2)
+                .unwrap()
+                .map(|transaction| transaction.fee)
+        );
+        let trial_balance = processor.get_trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert!(processor.get_discrepancies().is_empty());
+        assert_eq!(
+            btreemap! {
+                (LedgerAccount::ClientAvailable(1), Currency::Eur) => -11.0,
+                (LedgerAccount::ClientHeld(1), Currency::Eur) => 0.0,
+                (LedgerAccount::ExternalSettlement, Currency::Eur) => -90.0,
+                (LedgerAccount::Fees, Currency::Eur) => 1.0,
+                (LedgerAccount::ChargebackLoss, Currency::Eur) => 100.0,
+            },
+            trial_balance.balances
+        );
+    }
+    #[tokio::test]
+    async fn test_trial_balance_writes_discrepancies() {
+        // an account the journal knows nothing about
+        let mut store = InMemoryLedgerStore::new();
+        store
+            .put_account(Account::new(1, Currency::Eur, 10.0, 0.0, false))
+            .unwrap();
+        let processor =
+            TransactionProcessor::with_store(create_transaction_receiver(vec![]), Box::new(store));
+        let (mut out, mut warnings) = (vec![], vec![]);
+        processor
+            .write_trial_balance(&mut out, &mut warnings)
+            .unwrap();
+        assert_eq!(
+            "account,currency,balance\n",
+            String::from_utf8(out).unwrap()
+        );
+        assert_eq!(
+            "client 1 EUR does not reconcile: available 0.0000 and held 0.0000 in the journal, \
+             10.0000 and 0.0000 on the account\n",
+            String::from_utf8(warnings).unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn test_fees_on_house_client() {
+        let mut fee_schedule = FeeSchedule::new();
+        fee_schedule.house_client_id = Some(1);
+        fee_schedule.insert(
+            TransactionType::Deposit,
+            FeeTier {
+                from_amount: 0.0,
+                flat: 0.5,
+                percentage: 0.0,
+            },
+        );
+        let deposit = deposit(1, 2, 100.0);
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            deposit,
+            // would credit the house account twice in one update
+            Transaction {
+                id: 2,
+                client_id: 1,
+                ..deposit
+            },
+            Transaction {
+                id: 3,
+                of_type: TransactionType::Transfer,
+                amount: 10.0,
+                destination_client_id: Some(1),
+                ..deposit
+            },
+        ]))
+        .with_fee_schedule(fee_schedule.clone())
+        .unwrap();
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 0.5, 0.0, false),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 99.5, 0.0, false)
+            },
+            processor.get_account_map()
+        );
+        assert_eq!(
+            2,
+            processor
+                .get_outcomes()
+                .count("rejected", Some("client is the house account"))
+        );
+        assert!(processor.get_trial_balance().is_balanced());
+        assert!(processor.get_discrepancies().is_empty());
+        // a client with transactions of its own can not become the house
+        let mut store = InMemoryLedgerStore::new();
+        store
+            .append_history(HistoryEntry::accepted(
+                deposit,
+                Account::new(2, Currency::Eur, 100.0, 0.0, false),
+            ))
+            .unwrap();
+        fee_schedule.house_client_id = Some(2);
+        assert!(TransactionProcessor::with_store(
+            create_transaction_receiver(vec![]),
+            Box::new(store)
+        )
+        .with_fee_schedule(fee_schedule)
+        .is_err());
+    }
+    #[tokio::test]
+    async fn test_no_house_client_without_fees() {
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            deposit(1, ClientId::MAX, 10.0),
+            withdrawal(2, ClientId::MAX, 4.0),
+        ]))
+        .with_fee_schedule(FeeSchedule::new())
+        .unwrap();
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {(ClientId::MAX, Currency::Eur) => Account::new(ClientId::MAX, Currency::Eur, 6.0, 0.0, false)},
+            processor.get_account_map()
+        );
+        assert!(processor.get_discrepancies().is_empty());
+    }
+    #[tokio::test]
+    async fn test_client_limits() {
+        let client_limits = ClientLimitsTable::load("test-client-limits.csv").unwrap();
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 10.0),
+            withdrawal(2, 1, 40.0),
+            // the overdraft covers only 50 below zero
+            withdrawal(3, 1, 30.0),
+            deposit(4, 2, 500.0),
+            withdrawal(5, 2, 150.0),
+            deposit(6, 3, 500.0),
+            withdrawal(7, 3, 100.0),
+            withdrawal(8, 3, 60.0),
+            withdrawal(9, 3, 50.0),
+            withdrawal(10, 4, 10.0),
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .with_client_limits(client_limits);
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, -30.0, 0.0, false),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 500.0, 0.0, false),
+                (3, Currency::Eur) => Account::new(3, Currency::Eur, 350.0, 0.0, false),
+            },
+            processor.get_account_map()
+        );
+        let rejections = |client_id| {
+            processor
+                .get_store()
+                .client_history(client_id)
+                .unwrap()
+                .into_iter()
+                .filter_map(|entry| Some((entry.transaction.id, entry.reason?)))
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![(3, "overdraft limit exceeded".to_string())],
+            rejections(1)
+        );
+        assert_eq!(
+            vec![(
+                5,
+                "withdrawal exceeds the maximum single withdrawal".to_string()
+            )],
+            rejections(2)
+        );
+        assert_eq!(
+            vec![(8, "daily withdrawal cap exceeded".to_string())],
+            rejections(3)
+        );
+        assert_eq!(vec![(10, "insufficient funds".to_string())], rejections(4));
+    }
+    #[tokio::test]
+    async fn test_rules() {
+        let rules = RulesEngine::load("test-rules.csv").unwrap();
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 100.0),
+            withdrawal(2, 1, 10.0),
+            withdrawal(3, 1, 10.0),
+            withdrawal(4, 1, 10.0),
+            deposit(5, 2, 2000.0),
+            withdrawal(6, 2, 10.0),
+            deposit(7, 3, 10.0),
+            deposit(8, 3, 10.0),
+            dispute(7, 3),
+        ];
+        let mut processor =
+            TransactionProcessor::new(create_transaction_receiver(transactions)).with_rules(rules);
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 80.0, 0.0, false),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 2000.0, 0.0, false),
+                (3, Currency::Eur) => Account::new(3, Currency::Eur, 10.0, 10.0, false),
+            },
+            processor.get_account_map()
+        );
+        let last_entry = |client_id| {
+            processor
+                .get_store()
+                .client_history(client_id)
+                .unwrap()
+                .pop()
+                .unwrap()
+        };
+        let entry = last_entry(1);
+        assert!(!entry.accepted && !entry.held);
+        assert_eq!(Some("withdrawal burst".to_string()), entry.reason);
+        let entry = last_entry(2);
+        assert!(!entry.accepted && entry.held);
+        assert_eq!(
+            Some("cash out after large deposit".to_string()),
+            entry.reason
+        );
+        let entry = last_entry(3);
+        assert!(entry.accepted);
+        assert_eq!(vec!["frequent disputes".to_string()], entry.flags);
+    }
+    #[tokio::test]
+    async fn test_release_held_transaction() {
+        let rules = RulesEngine::load("test-rules.csv").unwrap();
+        let deposit = deposit(1, 1, 2000.0);
+        let release = Transaction {
+            id: 2,
+            of_type: TransactionType::Release,
+            amount: 0.0,
+            ..deposit
+        };
+        let transactions: Vec<Transaction> = vec![
+            deposit,
+            Transaction {
+                id: 2,
+                of_type: TransactionType::Withdrawal,
+                amount: 500.0,
+                ..deposit
+            },
+            release,
+            release,
+            Transaction { id: 3, ..release },
+        ];
+        let mut processor =
+            TransactionProcessor::new(create_transaction_receiver(transactions)).with_rules(rules);
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 1500.0, 0.0, false),
+            },
+            processor.get_account_map()
+        );
+        let history = processor.get_store().client_history(1).unwrap();
+        assert!(history[1].held);
+        assert!(history[2].accepted);
+        assert_eq!(TransactionType::Withdrawal, history[2].transaction.of_type);
+        // the release itself is recorded with the account state it left behind
+        assert_eq!(
+            HistoryEntry::accepted(release, history[2].account),
+            history[3]
+        );
+        assert_eq!(
+            2,
+            processor.get_outcomes().count(
+                "rejected",
+                Some("transaction does not exist or is not held")
+            )
+        );
+        assert!(processor.get_discrepancies().is_empty());
+    }
+    #[tokio::test]
+    async fn test_release_held_dispute() {
+        let mut rules = RulesEngine::new();
+        rules.insert(Rule {
+            name: "any dispute".to_string(),
+            kind: RuleKind::DisputeRate,
+            action: RuleAction::Hold,
+            threshold: 0.0,
+            window: 1,
+        });
+        let deposit = deposit(1, 1, 10.0);
+        let release = Transaction {
+            of_type: TransactionType::Release,
+            amount: 0.0,
+            ..deposit
+        };
+        let transactions: Vec<Transaction> = vec![
+            deposit,
+            Transaction {
+                of_type: TransactionType::Dispute,
+                amount: 0.0,
+                ..deposit
+            },
+            release,
+            release,
+        ];
+        let mut processor =
+            TransactionProcessor::new(create_transaction_receiver(transactions)).with_rules(rules);
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 0.0, 10.0, false),
+            },
+            processor.get_account_map()
+        );
+        assert_eq!(
+            vec![
+                (TransactionType::Deposit, "accepted"),
+                (TransactionType::Dispute, "held"),
+                (TransactionType::Dispute, "accepted"),
+                (TransactionType::Release, "accepted"),
+                (TransactionType::Release, "rejected"),
+            ],
+            processor
+                .get_store()
+                .client_history(1)
+                .unwrap()
+                .iter()
+                .map(|entry| (entry.transaction.of_type, entry.status()))
+                .collect::<Vec<_>>()
+        );
+    }
+    #[tokio::test]
+    async fn test_lock_policies() {
+        let lock_policies = LockPolicies::load("test-lock-policies.csv").unwrap();
+        let mut client_limits = ClientLimitsTable::new();
+        client_limits.insert(
+            2,
+            Currency::Eur,
+            ClientLimits {
+                overdraft_limit: 50.0,
+                ..ClientLimits::default()
+            },
+        );
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 100.0),
+            deposit(2, 1, 50.0),
+            dispute(1, 1),
+            dispute(2, 1),
+            // within the overdraft limit, the total is only negative past it
+            withdrawal(3, 2, 20.0),
+            withdrawal(4, 2, 30.0),
+            // a disputed withdrawal frees funds to withdraw beyond the limit
+            dispute(3, 2),
+            withdrawal(6, 2, 20.0),
+            unlock(7, 2),
+            deposit(5, 3, 150.0),
+            dispute(5, 3),
+            chargeback(5, 3),
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .with_client_limits(client_limits)
+            .with_lock_policies(lock_policies);
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account {
+                    lock_reason: Some(LockPolicy::OpenDisputes),
+                    ..Account::new(1, Currency::Eur, 0.0, 150.0, true)
+                },
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, -50.0, -20.0, false),
+                // locked by the chargeback itself, not by a policy
+                (3, Currency::Eur) => Account::new(3, Currency::Eur, 0.0, 0.0, true),
+            },
+            processor.get_account_map()
+        );
+        let locked_by = |client_id| {
+            processor
+                .get_store()
+                .client_history(client_id)
+                .unwrap()
+                .into_iter()
+                .map(|entry| entry.account.lock_reason)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(
+            vec![None, None, None, Some(LockPolicy::NegativeBalance), None],
+            locked_by(2)
+        );
+        assert_eq!(
+            vec![RiskCounters {
+                open_disputes: 0,
+                chargeback_amount: 150.0,
+                ..RiskCounters::new(3, Currency::Eur)
+            }],
+            processor.get_store().client_risk_counters(3).unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn test_authorize_capture_and_void() {
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 100.0),
+            authorize(2, 1, 30.0),
+            authorize(3, 1, 20.0),
+            // only 50 is left available, the rest is held by the authorizations
+            authorize(4, 1, 60.0),
+            capture(2, 1),
+            void(3, 1),
+            // a voided authorization can not be captured anymore
+            capture(3, 1),
+            dispute(2, 1),
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions));
+        let processor = processor.execute().await;
+        // the captured authorization is disputed like a withdrawal
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 100.0, -30.0, false)
+            },
+            processor.get_account_map()
+        );
+        assert_eq!(
+            Some(TransactionType::Withdrawal),
+            processor
+                .get_store()
+                .get_transaction(2)
+                .unwrap()
+                .map(|transaction| transaction.of_type)
+        );
+        assert_eq!(
+            Some(TransactionType::Void),
+            processor
+                .get_store()
+                .get_transaction(3)
+                .unwrap()
+                .map(|transaction| transaction.of_type)
+        );
+        assert_eq!(None, processor.get_store().get_transaction(4).unwrap());
+        let trial_balance = processor.get_trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert!(processor.get_discrepancies().is_empty());
+        assert_eq!(
+            btreemap! {
+                (LedgerAccount::ClientAvailable(1), Currency::Eur) => 100.0,
+                (LedgerAccount::ClientHeld(1), Currency::Eur) => -30.0,
+                (LedgerAccount::ExternalSettlement, Currency::Eur) => -70.0,
+            },
+            trial_balance.balances
+        );
+    }
+    #[tokio::test]
+    async fn test_void_releases_daily_withdrawal_cap() {
+        let mut client_limits = ClientLimitsTable::new();
+        client_limits.insert(
+            1,
+            Currency::Eur,
+            ClientLimits {
+                daily_withdrawal_cap: Some(100.0),
+                ..ClientLimits::default()
+            },
+        );
+        let deposit = deposit(1, 1, 500.0);
+        let authorize = Transaction {
+            of_type: TransactionType::Authorize,
+            amount: 80.0,
+            ..deposit
+        };
+        let transactions: Vec<Transaction> = vec![
+            deposit,
+            Transaction { id: 2, ..authorize },
+            // the void gives the 80 back to the day's cap
+            Transaction {
+                id: 2,
+                of_type: TransactionType::Void,
+                amount: 0.0,
+                ..deposit
+            },
+            Transaction {
+                id: 3,
+                of_type: TransactionType::Withdrawal,
+                amount: 50.0,
+                ..deposit
+            },
+            // the capture keeps its 30 counted next to the 50 withdrawn
+            Transaction {
+                id: 4,
+                amount: 30.0,
+                ..authorize
+            },
+            Transaction {
+                id: 4,
+                of_type: TransactionType::Capture,
+                amount: 0.0,
+                ..deposit
+            },
+            Transaction {
+                id: 5,
+                of_type: TransactionType::Withdrawal,
+                amount: 30.0,
+                ..deposit
+            },
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .with_client_limits(client_limits)
+            .with_clock(NaiveDate::from_ymd_opt(2024, 3, 1).unwrap());
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 420.0, 0.0, false)
+            },
+            processor.get_account_map()
+        );
+        assert_eq!(
+            1,
+            processor
+                .get_outcomes()
+                .count("rejected", Some("daily withdrawal cap exceeded"))
+        );
+        // the captured authorization is not mistaken for a scheduled transaction
+        let captured = processor.get_store().get_transaction(4).unwrap().unwrap();
+        assert_eq!(
+            (None, NaiveDate::from_ymd_opt(2024, 3, 1)),
+            (captured.effective_at, captured.authorized_on)
+        );
+        assert!(processor.get_discrepancies().is_empty());
+    }
+    #[tokio::test]
+    async fn test_transfer_limits_and_risk() {
+        let mut client_limits = ClientLimitsTable::new();
+        client_limits.insert(
+            1,
+            Currency::Eur,
+            ClientLimits {
+                max_withdrawal: Some(50.0),
+                ..ClientLimits::default()
+            },
+        );
+        let lock_policies = LockPolicies::load("test-lock-policies.csv").unwrap();
+        let deposit = deposit(1, 1, 200.0);
+        let transfer = Transaction {
+            of_type: TransactionType::Transfer,
+            amount: 40.0,
+            destination_client_id: Some(2),
+            ..deposit
+        };
+        let dispute = Transaction {
+            of_type: TransactionType::Dispute,
+            amount: 0.0,
+            ..deposit
+        };
+        let transactions: Vec<Transaction> = vec![
+            deposit,
+            Transaction { id: 2, ..transfer },
+            Transaction { id: 3, ..transfer },
+            Transaction {
+                id: 4,
+                amount: 60.0,
+                ..transfer
+            },
+            // the disputed funds are held on the receiving client, so are the risk and the lock
+            Transaction { id: 2, ..dispute },
+            Transaction { id: 3, ..dispute },
+        ];
+        let today = NaiveDate::from_ymd_opt(2024, 3, 1).unwrap();
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .with_client_limits(client_limits)
+            .with_lock_policies(lock_policies)
+            .with_clock(today);
+        let processor = processor.execute().await;
+        let accounts = processor.get_account_map();
+        assert_eq!(
+            Account::new(1, Currency::Eur, 120.0, 0.0, false),
+            accounts[&(1, Currency::Eur)]
+        );
+        assert_eq!(
+            Account {
+                lock_reason: Some(LockPolicy::OpenDisputes),
+                ..Account::new(2, Currency::Eur, 0.0, 80.0, true)
+            },
+            accounts[&(2, Currency::Eur)]
+        );
+        assert_eq!(
+            1,
+            processor.get_outcomes().count(
+                "rejected",
+                Some("withdrawal exceeds the maximum single withdrawal")
+            )
+        );
+        assert_eq!(
+            80.0,
+            processor
+                .get_store()
+                .get_daily_withdrawal(1, Currency::Eur, today)
+                .unwrap()
+        );
+        assert!(processor
+            .get_store()
+            .client_risk_counters(1)
+            .unwrap()
+            .is_empty());
+        assert_eq!(
+            vec![RiskCounters {
+                open_disputes: 2,
+                ..RiskCounters::new(2, Currency::Eur)
+            }],
+            processor.get_store().client_risk_counters(2).unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn test_transfer() {
+        let transactions: Vec<Transaction> = vec![
+            deposit(1, 1, 100.0),
+            transfer(2, 1, 2, 40.0),
+            deposit(3, 3, 10.0),
+            dispute(3, 3),
+            chargeback(3, 3),
+            transfer(4, 1, 3, 10.0),
+            transfer(5, 1, 2, 100.0),
+            dispute(2, 1),
+            chargeback(2, 1),
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions));
+        let processor = processor.execute().await;
+        // the charged back transfer went back to the sender and locked the receiver
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 100.0, 0.0, false),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 0.0, 0.0, true),
+                (3, Currency::Eur) => Account::new(3, Currency::Eur, 0.0, 0.0, true),
+            },
+            processor.get_account_map()
+        );
+        let history = processor.get_store().client_history(1).unwrap();
+        assert_eq!(
+            vec![
+                None,
+                None,
+                Some("destination account is locked".to_string()),
+                Some("insufficient funds".to_string()),
+                None,
+                None,
+            ],
+            history
+                .into_iter()
+                .map(|entry| entry.reason)
+                .collect::<Vec<_>>()
+        );
+        // the receiving client sees the transfer in its own history
+        assert_eq!(
+            Some(TransactionType::Transfer),
+            processor
+                .get_store()
+                .client_history(2)
+                .unwrap()
+                .first()
+                .map(|entry| entry.transaction.of_type)
+        );
+        let trial_balance = processor.get_trial_balance();
+        assert!(trial_balance.is_balanced());
+        assert!(processor.get_discrepancies().is_empty());
+        assert_eq!(
+            Some(&0.0),
+            trial_balance
+                .balances
+                .get(&(LedgerAccount::ClientHeld(2), Currency::Eur))
+        );
+    }
+    #[tokio::test]
+    async fn test_clock_follows_booking_dates() {
+        let deposit = Transaction {
+            booked_on: NaiveDate::from_ymd_opt(2024, 1, 1),
+            ..deposit(1, 1, 10.0)
+        };
+        let withdrawal = Transaction {
+            of_type: TransactionType::Withdrawal,
+            amount: 12.0,
+            ..deposit
+        };
+        let transactions: Vec<Transaction> = vec![
+            deposit,
+            Transaction {
+                id: 2,
+                amount: 5.0,
+                effective_at: NaiveDate::from_ymd_opt(2024, 1, 3),
+                ..deposit
+            },
+            Transaction {
+                id: 3,
+                amount: 1.0,
+                booked_on: NaiveDate::from_ymd_opt(2024, 1, 2),
+                ..deposit
+            },
+            // the scheduled deposit is not due yet on the 2nd
+            Transaction {
+                id: 4,
+                booked_on: NaiveDate::from_ymd_opt(2024, 1, 2),
+                ..withdrawal
+            },
+            Transaction {
+                id: 5,
+                booked_on: NaiveDate::from_ymd_opt(2024, 1, 3),
+                ..withdrawal
+            },
+        ];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions))
+            .with_clock(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        let processor = processor.execute().await;
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 4.0, 0.0, false)
+            },
+            processor.get_account_map()
+        );
+        // the statement shows the deposit as scheduled when it comes in and again once it runs
+        assert_eq!(
+            vec![
+                (1, "accepted"),
+                (2, "scheduled"),
+                (3, "accepted"),
+                (4, "rejected"),
+                (2, "accepted"),
+                (5, "accepted")
+            ],
+            processor
+                .get_store()
+                .client_history(1)
+                .unwrap()
+                .iter()
+                .map(|entry| (entry.transaction.id, entry.status()))
+                .collect::<Vec<_>>()
+        );
+        assert!(processor.get_store().scheduled().unwrap().is_empty());
+        assert_eq!(1, processor.get_outcomes().count("scheduled", None));
+        assert_eq!(
+            btreemap! {"deposit".to_string() => 1},
+            processor.get_run_summary().scheduled
+        );
+        assert_eq!(
+            12.0,
+            processor
+                .get_store()
+                .get_daily_withdrawal(
+                    1,
+                    Currency::Eur,
+                    NaiveDate::from_ymd_opt(2024, 1, 3).unwrap()
+                )
+                .unwrap()
+        );
+    }
+    #[tokio::test]
+    async fn test_past_booking_dates_keep_the_wall_clock() {
+        let transactions: Vec<Transaction> = vec![Transaction {
+            effective_at: NaiveDate::from_ymd_opt(2024, 6, 1),
+            booked_on: NaiveDate::from_ymd_opt(2024, 1, 1),
+            ..deposit(1, 1, 10.0)
+        }];
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(transactions));
+        let processor = processor.execute().await;
+        // the effective date has passed by today, whatever the row was booked on
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 10.0, 0.0, false)
+            },
+            processor.get_account_map()
+        );
+        assert!(processor.get_store().scheduled().unwrap().is_empty());
+    }
+    #[tokio::test]
+    async fn test_accrue_interest() {
+        let interest_rates =
+            InterestRates::load("test-interest-rates.csv", Some("test-client-tiers.csv")).unwrap();
+        let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let transactions: Vec<Transaction> = vec![deposit(1, 1, 1000.0), deposit(2, 2, 1000.0)];
+        let mut processor =
+            TransactionProcessor::new(create_transaction_receiver(transactions)).with_clock(today);
+        processor.execute().await;
+        processor.accrue_interest(&interest_rates);
+        // a second run on the same day books nothing
+        processor.accrue_interest(&interest_rates);
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 1000.1, 0.0, false),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 1000.1, 0.0, false),
+            },
+            processor.get_account_map()
+        );
+        let interest = processor
+            .get_store()
+            .get_transaction(interest_transaction_id(1, Currency::Eur, today))
+            .unwrap()
+            .unwrap();
+        assert_eq!(TransactionType::Deposit, interest.of_type);
+        assert_eq!(0.1, interest.amount);
+        // the day is in the id, the deposit is not a scheduled one
+        assert_eq!(None, interest.effective_at);
+        // the deposit and one day of interest
+        assert_eq!(2, processor.get_store().client_history(1).unwrap().len());
+        // interest is a cost of the house, not money deposited from outside
+        let trial_balance = processor.get_trial_balance();
+        assert_eq!(
+            Some(&(-2.0 * 0.1f32 as f64)),
+            trial_balance
+                .balances
+                .get(&(LedgerAccount::InterestExpense, Currency::Eur))
+        );
+        assert_eq!(
+            Some(&-2000.0),
+            trial_balance
+                .balances
+                .get(&(LedgerAccount::ExternalSettlement, Currency::Eur))
+        );
+        assert!(trial_balance.is_balanced());
+    }
+    #[tokio::test]
+    async fn test_accrue_interest_skips_locked_clients() {
+        let interest_rates = InterestRates::load("test-interest-rates.csv", None).unwrap();
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            deposit(1, 1, 1000.0),
+            deposit(2, 2, 1000.0),
+            lock(3, 2),
+        ]))
+        .with_clock(NaiveDate::from_ymd_opt(2024, 1, 1).unwrap());
+        processor.execute().await;
+        processor.accrue_interest(&interest_rates);
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 1000.1, 0.0, false),
+                (2, Currency::Eur) => Account::new(2, Currency::Eur, 1000.0, 0.0, true),
+            },
+            processor.get_account_map()
+        );
+        // the skipped accrual is not a failed row
+        assert_eq!(0, processor.get_error_counts().rejected);
+        assert_eq!(
+            0,
+            processor.get_run_summary().rejected.values().sum::<u64>()
+        );
+    }
+    #[tokio::test]
+    async fn test_accrue_interest_without_fees() {
+        let interest_rates = InterestRates::load("test-interest-rates.csv", None).unwrap();
+        let mut fee_schedule = FeeSchedule::new();
+        fee_schedule.house_client_id = Some(9);
+        fee_schedule.insert(
+            TransactionType::Deposit,
+            FeeTier {
+                from_amount: 0.0,
+                flat: 0.5,
+                percentage: 0.0,
+            },
+        );
+        let today = NaiveDate::from_ymd_opt(2024, 1, 1).unwrap();
+        let mut processor =
+            TransactionProcessor::new(create_transaction_receiver(vec![deposit(1, 1, 1000.5)]))
+                .with_fee_schedule(fee_schedule)
+                .unwrap()
+                .with_clock(today);
+        processor.execute().await;
+        processor.accrue_interest(&interest_rates);
+        let interest = processor
+            .get_store()
+            .get_transaction(interest_transaction_id(1, Currency::Eur, today))
+            .unwrap()
+            .unwrap();
+        assert_eq!(0.0, interest.fee);
+        // the house only earned the fee of the partner deposit
+        assert_eq!(
+            hashmap! {
+                (1, Currency::Eur) => Account::new(1, Currency::Eur, 1000.1, 0.0, false),
+                (9, Currency::Eur) => Account::new(9, Currency::Eur, 0.5, 0.0, false),
+            },
+            processor.get_account_map()
+        );
+        assert!(processor.get_discrepancies().is_empty());
+    }
+    #[tokio::test]
+    async fn test_error_budget() {
+        let deposit = deposit(1, 1, 10.0);
+        let withdrawal = Transaction {
+            id: 2,
+            of_type: TransactionType::Withdrawal,
+            amount: 20.0,
+            ..deposit
+        };
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            sender.send(Ok(deposit)).await.unwrap();
+            sender.send(Err(anyhow!("corrupted"))).await.unwrap();
+            sender.send(Ok(withdrawal)).await.unwrap();
+            // never read, the budget is exceeded before it
+            sender.send(Ok(Transaction { id: 3, ..deposit })).await.ok();
+        });
+        let mut processor =
+            TransactionProcessor::new(receiver).with_error_budget(ErrorBudget::Count(1));
+        processor.execute().await;
+        assert!(processor.error_budget_exceeded());
+        assert_eq!(
+            ErrorCounts {
+                rows: 3,
+                corrupted: 1,
+                rejected: 1,
+            },
+            processor.get_error_counts()
+        );
+        // two failed rows of three are within a budget of 70%, not of 60%
+        for (percent, exceeded) in [(70.0, false), (60.0, true)] {
+            let (sender, receiver) = mpsc::channel(1);
+            tokio::spawn(async move {
+                sender.send(Ok(deposit)).await.unwrap();
+                sender.send(Err(anyhow!("corrupted"))).await.unwrap();
+                sender.send(Ok(withdrawal)).await.unwrap();
+            });
+            let mut processor = TransactionProcessor::new(receiver)
+                .with_error_budget(ErrorBudget::Percent(percent));
+            processor.execute().await;
+            assert_eq!(exceeded, processor.error_budget_exceeded());
+        }
+    }
+    #[tokio::test]
+    async fn test_outcomes() {
+        let deposit = deposit(1, 1, 10.0);
+        let withdrawal = Transaction {
+            id: 2,
+            of_type: TransactionType::Withdrawal,
+            amount: 20.0,
+            ..deposit
+        };
+        let mut processor = TransactionProcessor::new(create_transaction_receiver(vec![
+            deposit,
+            withdrawal,
+            Transaction {
+                id: 3,
+                ..withdrawal
+            },
+            Transaction {
+                id: 4,
+                amount: 5.0,
+                ..withdrawal
+            },
+        ]));
+        processor.execute().await;
+        let outcomes = processor.get_outcomes();
+        assert_eq!(2, outcomes.count("accepted", None));
+        assert_eq!(2, outcomes.count("rejected", Some("insufficient funds")));
+        assert_eq!(
+            vec![("accepted", "", 2), ("rejected", "insufficient funds", 2)],
+            outcomes.counts().collect::<Vec<_>>()
+        );
+    }
+    #[tokio::test]
+    async fn test_run_summary() {
+        let deposit = deposit(1, 1, 10.0);
+        let (sender, receiver) = mpsc::channel(1);
+        tokio::spawn(async move {
+            for transaction in [
+                deposit,
                 Transaction {
-                    id: 1,
-                    of_type: TransactionType::Deposit,
-                    client_id: 1,
-                    amount: 999_999_999.0,
-                    under_dispute: false,
+                    id: 2,
+                    amount: 4.0,
+                    ..deposit
                 },
                 Transaction {
-                    id: 1,
-                    of_type: TransactionType::Deposit,
-                    client_id: 1,
-                    amount: 1.01,
-                    under_dispute: false,
-                }
-            ]))
-            .execute()
-            .await
-            .get_account_map()
+                    id: 3,
+                    of_type: TransactionType::Withdrawal,
+                    amount: 20.0,
+                    ..deposit
+                },
+                Transaction {
+                    id: 4,
+                    of_type: TransactionType::Withdrawal,
+                    amount: 3.0,
+                    ..deposit
+                },
+                Transaction {
+                    id: 2,
+                    of_type: TransactionType::Dispute,
+                    amount: 0.0,
+                    ..deposit
+                },
+                Transaction {
+                    id: 2,
+                    of_type: TransactionType::Chargeback,
+                    amount: 0.0,
+                    ..deposit
+                },
+            ] {
+                sender.send(Ok(transaction)).await.unwrap();
+            }
+            sender.send(Err(anyhow!("corrupted"))).await.unwrap();
+        });
+        let mut processor = TransactionProcessor::new(receiver);
+        processor.execute().await;
+        let summary = processor.get_run_summary();
+        assert_eq!(
+            RunSummary {
+                rows_read: 7,
+                rows_parsed: 6,
+                rejected_at_parse: 1,
+                rows_skipped: 0,
+                accepted: btreemap! {
+                    "chargeback".to_string() => 1,
+                    "deposit".to_string() => 2,
+                    "dispute".to_string() => 1,
+                    "withdrawal".to_string() => 1,
+                },
+                rejected: btreemap! {"withdrawal".to_string() => 1},
+                held: btreemap! {},
+                scheduled: btreemap! {},
+                disputes_opened: 1,
+                disputes_resolved: 0,
+                disputes_charged_back: 1,
+                accounts_created: 1,
+                accounts_locked: 1,
+                deposited: btreemap! {Currency::Eur => 14.0},
+                withdrawn: btreemap! {Currency::Eur => 3.0},
+                elapsed_seconds: summary.elapsed_seconds,
+                rows_per_second: summary.rows_per_second,
+            },
+            summary
+        );
+        assert!(summary.elapsed_seconds > 0.0);
+    }
+    // a store whose writes start failing after a number of successful updates
+    #[derive(Debug)]
+    struct FailingStore {
+        store: InMemoryLedgerStore,
+        applies_left: usize,
+    }
+    impl LedgerStore for FailingStore {
+        fn get_account(
+            &self,
+            client_id: ClientId,
+            currency: Currency,
+        ) -> anyhow::Result<Option<Account>> {
+            self.store.get_account(client_id, currency)
+        }
+        fn client_accounts(&self, client_id: ClientId) -> anyhow::Result<Vec<Account>> {
+            self.store.client_accounts(client_id)
+        }
+        fn put_account(&mut self, account: Account) -> anyhow::Result<()> {
+            self.store.put_account(account)
+        }
+        fn get_transaction(&self, id: TransactionId) -> anyhow::Result<Option<Transaction>> {
+            self.store.get_transaction(id)
+        }
+        fn put_transaction(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+            self.store.put_transaction(transaction)
+        }
+        fn apply(&mut self, update: LedgerUpdate) -> anyhow::Result<()> {
+            if self.applies_left == 0 {
+                return Err(anyhow!("disk full"));
+            }
+            self.applies_left -= 1;
+            self.store.apply(update)
+        }
+        fn accounts(&self) -> anyhow::Result<Vec<Account>> {
+            self.store.accounts()
+        }
+        fn append_history(&mut self, entry: HistoryEntry) -> anyhow::Result<()> {
+            self.store.append_history(entry)
+        }
+        fn client_history(&self, client_id: ClientId) -> anyhow::Result<Vec<HistoryEntry>> {
+            self.store.client_history(client_id)
+        }
+        fn journal(&self) -> anyhow::Result<Vec<JournalEntry>> {
+            self.store.journal()
+        }
+        fn get_daily_withdrawal(
+            &self,
+            client_id: ClientId,
+            currency: Currency,
+            date: NaiveDate,
+        ) -> anyhow::Result<f32> {
+            self.store.get_daily_withdrawal(client_id, currency, date)
+        }
+        fn client_risk_counters(&self, client_id: ClientId) -> anyhow::Result<Vec<RiskCounters>> {
+            self.store.client_risk_counters(client_id)
+        }
+        fn schedule(&mut self, transaction: Transaction) -> anyhow::Result<()> {
+            self.store.schedule(transaction)
+        }
+        fn next_due(&self, on: NaiveDate) -> anyhow::Result<Option<ScheduledTransaction>> {
+            self.store.next_due(on)
+        }
+        fn scheduled(&self) -> anyhow::Result<Vec<Transaction>> {
+            self.store.scheduled()
+        }
+        fn get_checkpoint(&self, input: &str) -> anyhow::Result<Option<Checkpoint>> {
+            self.store.get_checkpoint(input)
+        }
+        fn put_checkpoint(&mut self, checkpoint: Checkpoint) -> anyhow::Result<()> {
+            self.store.put_checkpoint(checkpoint)
+        }
+        fn remove_checkpoint(&mut self, input: &str) -> anyhow::Result<()> {
+            self.store.remove_checkpoint(input)
+        }
+    }
+    #[tokio::test]
+    async fn test_summary_counts_only_applied_updates() {
+        let deposit = deposit(1, 1, 10.0);
+        let transactions = vec![
+            deposit,
+            Transaction {
+                of_type: TransactionType::Dispute,
+                amount: 0.0,
+                ..deposit
+            },
+            Transaction {
+                id: 2,
+                of_type: TransactionType::Withdrawal,
+                amount: 100.0,
+                ..deposit
+            },
+        ];
+        let metrics = Metrics::shared();
+        let mut processor = TransactionProcessor::with_store(
+            create_transaction_receiver(transactions),
+            Box::new(FailingStore {
+                store: InMemoryLedgerStore::new(),
+                applies_left: 1,
+            }),
+        )
+        .with_metrics(metrics.clone())
+        .unwrap();
+        processor.execute().await;
+        let summary = processor.get_run_summary();
+        // the dispute and the rejected withdrawal were never written
+        assert_eq!(
+            RunSummary {
+                rows_read: 3,
+                rows_parsed: 3,
+                accepted: btreemap! {"deposit".to_string() => 1},
+                accounts_created: 1,
+                deposited: btreemap! {Currency::Eur => 10.0},
+                elapsed_seconds: summary.elapsed_seconds,
+                rows_per_second: summary.rows_per_second,
+                ..RunSummary::default()
+            },
+            summary
+        );
+        assert_eq!(
+            vec![("accepted", "", 1)],
+            processor.get_outcomes().counts().collect::<Vec<_>>()
         );
+        let text = metrics.lock().unwrap().render();
+        for line in [
+            "transaction_resolution_transactions_total{type=\"deposit\",status=\"accepted\",reason=\"\"} 1",
+            "transaction_resolution_open_disputes 0",
+        ] {
+            assert!(text.lines().any(|l| l == line), "missing {}", line);
+        }
+        assert!(!text.contains("type=\"dispute\""));
+        assert!(!text.contains("type=\"withdrawal\""));
     }
 }
@@ -0,0 +1,65 @@
+use crate::transaction::TransactionType;
+use anyhow::anyhow;
+use csv::{ReaderBuilder, Trim};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+struct TypeAliasDTO {
+    alias: String,
+    #[serde(rename = "type")]
+    of_type: TransactionType,
+}
+
+// Partner specific names of transaction types. Names and aliases are matched in any case.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TypeAliases {
+    alias_map: HashMap<String, TransactionType>,
+}
+
+impl Default for TypeAliases {
+    fn default() -> TypeAliases {
+        let mut type_aliases = TypeAliases {
+            alias_map: HashMap::new(),
+        };
+        // common enough to be understood without an aliases file
+        type_aliases.insert("withdraw", TransactionType::Withdrawal);
+        type_aliases
+    }
+}
+
+impl TypeAliases {
+    pub fn new() -> TypeAliases {
+        TypeAliases::default()
+    }
+    pub fn load(path: &str) -> anyhow::Result<TypeAliases> {
+        let mut reader = ReaderBuilder::new().trim(Trim::All).from_path(path)?;
+        let mut type_aliases = TypeAliases::new();
+        for result in reader.deserialize() {
+            let dto: TypeAliasDTO = result?;
+            // an alias must not change what a canonical name means
+            if let Ok(of_type) = dto.alias.parse::<TransactionType>() {
+                return Err(anyhow!(
+                    "Invalid type alias: {}, already the name of {}",
+                    dto.alias,
+                    of_type
+                ));
+            }
+            type_aliases.insert(&dto.alias, dto.of_type);
+        }
+        Ok(type_aliases)
+    }
+    pub fn insert(&mut self, alias: &str, of_type: TransactionType) {
+        self.alias_map.insert(alias.to_lowercase(), of_type);
+    }
+    pub fn parse(&self, name: &str) -> anyhow::Result<TransactionType> {
+        match name.parse::<TransactionType>() {
+            Ok(of_type) => Ok(of_type),
+            Err(_) => self
+                .alias_map
+                .get(&name.to_lowercase())
+                .copied()
+                .ok_or_else(|| anyhow!("Invalid transaction: unknown type {}", name)),
+        }
+    }
+}
@@ -0,0 +1,37 @@
+#![cfg(test)]
+mod tests {
+    use crate::transaction::TransactionType;
+    use crate::type_aliases::TypeAliases;
+
+    #[test]
+    fn test_parse_canonical_names() {
+        let type_aliases = TypeAliases::new();
+        for name in ["deposit", "Deposit", "DEPOSIT"] {
+            assert_eq!(TransactionType::Deposit, type_aliases.parse(name).unwrap());
+        }
+        assert_eq!(
+            TransactionType::Withdrawal,
+            type_aliases.parse("Withdraw").unwrap()
+        );
+        assert_eq!(
+            "Invalid transaction: unknown type credit",
+            type_aliases.parse("credit").unwrap_err().to_string()
+        );
+    }
+    #[test]
+    fn test_load_aliases() {
+        let type_aliases = TypeAliases::load("test-type-aliases.csv").unwrap();
+        assert_eq!(
+            TransactionType::Deposit,
+            type_aliases.parse("credit").unwrap()
+        );
+        assert_eq!(
+            TransactionType::Withdrawal,
+            type_aliases.parse("DEBIT").unwrap()
+        );
+        assert_eq!(
+            TransactionType::Withdrawal,
+            type_aliases.parse("withdraw").unwrap()
+        );
+    }
+}